@@ -8,17 +8,29 @@
 //!
 //! bffextract CLI tool to extract or list content of BFF files (Backup File Format).
 
-use bfflib::archive::{Archive, Record};
+use bfflib::archive::{Archive, ExtractionReport, Record, RecordIter};
 use bfflib::attribute;
+use bfflib::carve;
+use bfflib::charset::Charset;
+use bfflib::compression::{self, Compression};
+use bfflib::info::ArchiveInfo;
+use bfflib::inspect::{self, Field, RecordDump};
+use bfflib::mksysb;
+use bfflib::pack::PackReader;
+use bfflib::research::{Corpus, Example, Observation};
+use bfflib::stream::{ForwardReader, OffsetReader};
+use bfflib::tape::{TapeArchive, TapeArchives};
+use bfflib::volume::VolumeReader;
 use bfflib::{Error, Result};
-use clap::Parser;
-use comfy_table::{presets, CellAlignment, Row, Table};
+use clap::{Parser, Subcommand};
+use comfy_table::{CellAlignment, Row, Table, presets};
 use core::result::Result as StdResult;
-use std::io::BufReader;
+use filetime::{FileTime, set_file_times};
+use std::io::{BufReader, BufWriter};
 use std::path::{Path, PathBuf};
 use std::{
     fs::File,
-    io::{Read, Seek},
+    io::{self, Read, Seek},
 };
 #[cfg(unix)]
 use users::{Groups, Users, UsersCache};
@@ -34,22 +46,104 @@ fn parse_attributes(value: &str) -> StdResult<u8, String> {
                 'p' => Ok(acc | attribute::ATTRIBUTE_PERMISSIONS),
                 'o' => Ok(acc | attribute::ATTRIBUTE_OWNERS),
                 't' => Ok(acc | attribute::ATTRIBUTE_TIMESTAMPS),
-                _ => return Err(format!("Invalid attribute '{ch}'.")),
+                _ => Err(format!("Invalid attribute '{ch}'.")),
             }
             #[cfg(windows)]
             match ch {
                 'n' => Ok(acc | attribute::ATTRIBUTE_NONE),
                 't' => Ok(acc | attribute::ATTRIBUTE_TIMESTAMPS),
-                _ => return Err(format!("Invalid attribute '{ch}'.")),
+                _ => Err(format!("Invalid attribute '{ch}'.")),
             }
         })
 }
 
+/// Definition of the command line: either one of the subcommands or the arguments to list or
+/// extract a BFF archive.
+#[derive(Parser, Debug)]
+#[command(
+    about,
+    version,
+    author,
+    args_conflicts_with_subcommands = true,
+    subcommand_negates_reqs = true
+)]
+struct Cli {
+    #[command(subcommand)]
+    command: Option<Command>,
+
+    #[command(flatten)]
+    args: Option<Args>,
+}
+
+/// Subcommands besides listing and extracting BFF archives
+#[derive(Subcommand, Debug)]
+enum Command {
+    /// Unpack files compressed by the Unix `pack` command (.z files).
+    Unpack {
+        #[arg(required = true, help = "Path to packed file(s).")]
+        filenames: Vec<PathBuf>,
+
+        #[arg(
+            short = 'C',
+            long,
+            help = "Write unpacked files to directory. Defaults to the directory of each packed file."
+        )]
+        chdir: Option<PathBuf>,
+    },
+    /// Find BFF archives embedded in arbitrary data, e.g. firmware images, and extract them.
+    Carve {
+        #[arg(help = "Path to the file to search.")]
+        filename: PathBuf,
+
+        #[arg(
+            short = 'C',
+            long,
+            default_value = ".",
+            help = "Extract each archive to a numbered directory in this directory."
+        )]
+        chdir: PathBuf,
+
+        #[arg(
+            short = 't',
+            long,
+            default_value_t = false,
+            help = "List found archives only."
+        )]
+        list: bool,
+    },
+    /// Print the metadata of a BFF archive and statistics of its records.
+    Info {
+        #[arg(help = "Path to BFF file.")]
+        filename: PathBuf,
+    },
+    /// Dump the raw fields of all records for format research, including rejected records.
+    Inspect {
+        #[arg(help = "Path to BFF file.")]
+        filename: PathBuf,
+
+        #[arg(long, default_value_t = false, help = "Print JSON instead of text.")]
+        json: bool,
+    },
+    /// Collect statistics of the unknown header fields of all BFF files in a directory.
+    Research {
+        #[arg(help = "Directory to search BFF files in recursively.")]
+        directory: PathBuf,
+
+        #[arg(long, default_value_t = false, help = "Print JSON instead of CSV.")]
+        json: bool,
+    },
+    /// Check the integrity of a BFF archive. Exits with an error if problems are found.
+    Verify {
+        #[arg(help = "Path to BFF file.")]
+        filename: PathBuf,
+    },
+}
+
 /// Definition of command line arguments
 #[derive(Parser, Debug)]
 #[command(about, version, author)]
 struct Args {
-    #[arg(help = "Path to BFF file.")]
+    #[arg(help = "Path to BFF file. Use '-' to read from stdin.")]
     filename: PathBuf,
 
     #[arg(value_delimiter = ' ', num_args = 0.., help = "Extract specific source file(s) and folders recursively only.")]
@@ -82,7 +176,7 @@ struct Args {
         short = 'v',
         long,
         default_value_t = false,
-        help = "Displays details while extracting."
+        help = "Displays details while extracting or listing."
     )]
     verbose: bool,
 
@@ -101,6 +195,45 @@ struct Args {
         help = "Print ACL of records in AIX text format."
     )]
     acl: bool,
+
+    #[arg(
+        long,
+        default_value_t = false,
+        help = "Read FILENAME and FILE_LIST as volumes of one archive in order."
+    )]
+    multi_volume: bool,
+
+    #[arg(
+        long,
+        value_name = "N",
+        value_parser = clap::value_parser!(u64).range(1..),
+        help = "Read archive N of a tape image containing several archives."
+    )]
+    archive: Option<u64>,
+
+    #[arg(
+        long,
+        default_value_t = false,
+        conflicts_with = "archive",
+        help = "Read the volume group backup of a mksysb or savevg image."
+    )]
+    mksysb: bool,
+
+    #[arg(
+        long,
+        default_value_t = false,
+        help = "Search the next record after damaged records and report the skipped data."
+    )]
+    recover: bool,
+
+    #[arg(
+        long,
+        value_name = "CHARSET",
+        help = concat!("Convert record names from this character set to UTF-8.\n",
+               "Possible values: ISO8859-1, ISO8859-15, IBM-943 (Shift-JIS),\n",
+               "                 IBM-eucJP, UTF-8")
+    )]
+    from_charset: Option<Charset>,
 }
 
 /// Helper to implement different user data retrivals by target OS.
@@ -180,14 +313,9 @@ fn record_matches_filter<P: AsRef<Path>>(record: &Record, filter_list: &[P]) ->
 }
 
 /// Print ACL entries for all matching records in AIX text format.
-fn print_acls<R: Read + Seek, P: AsRef<Path>>(
-    archive: &Archive<R>,
-    filter_list: &[P],
-    numeric: bool,
-) {
+fn print_acls<P: AsRef<Path>>(records: &[Record], filter_list: &[P], numeric: bool) {
     let user_data = UserData::new();
-    let records = archive
-        .records()
+    let records = records
         .iter()
         .filter(|record| record.acl().is_some() && record_matches_filter(record, filter_list));
 
@@ -204,20 +332,40 @@ fn print_acls<R: Read + Seek, P: AsRef<Path>>(
 }
 
 /// Print content of BFF file for CLI output
-fn print_content<R: Read + Seek, P: AsRef<Path>>(
-    archive: &Archive<R>,
+fn print_content<P: AsRef<Path>>(
+    records: &[Record],
     filter_list: &[P],
     numeric: bool,
+    verbose: bool,
 ) {
+    println!("{}", content_table(records, filter_list, numeric, verbose));
+}
+
+/// Build the table listing the records. `verbose` adds the kind, link count, inode and device of
+/// each record.
+fn content_table<P: AsRef<Path>>(
+    records: &[Record],
+    filter_list: &[P],
+    numeric: bool,
+    verbose: bool,
+) -> Table {
     let date_format = "%Y-%m-%d %H:%M:%S";
     let mut table = Table::new();
-    table.set_header(Row::from(vec![
-        "Mode", "UID", "GID", "Size", "Modified", "Filename",
-    ]));
+    let mut header = vec!["Mode", "UID", "GID", "Size", "Modified"];
+    if verbose {
+        header.extend(["Kind", "Links", "Inode", "Device"]);
+    }
+    header.push("Filename");
+    table.set_header(Row::from(header));
     // Disable all table borders
     table.load_preset(presets::NOTHING);
     // Set columns right aligned
-    [1, 2, 3].iter().for_each(|&col| {
+    let right_aligned: &[usize] = if verbose {
+        &[1, 2, 3, 6, 7]
+    } else {
+        &[1, 2, 3]
+    };
+    right_aligned.iter().for_each(|&col| {
         table
             .column_mut(col)
             .unwrap()
@@ -225,8 +373,7 @@ fn print_content<R: Read + Seek, P: AsRef<Path>>(
     });
 
     let user_data = UserData::new();
-    let records = archive
-        .records()
+    let records = records
         .iter()
         .filter(|record| record_matches_filter(record, filter_list));
     for record in records {
@@ -239,7 +386,7 @@ fn print_content<R: Read + Seek, P: AsRef<Path>>(
             None => filename,
         };
 
-        table.add_row(vec![
+        let mut row = vec![
             format!(
                 "{}{}",
                 record.mode(),
@@ -249,35 +396,38 @@ fn print_content<R: Read + Seek, P: AsRef<Path>>(
             groupname,
             format!("{}", record.size()),
             record.mdate().format(date_format).to_string(),
-            print_filename,
-        ]);
+        ];
+        if verbose {
+            row.extend([
+                record.kind().to_string(),
+                record.link_count().to_string(),
+                record.inode().to_string(),
+                record.device().to_string(),
+            ]);
+        }
+        row.push(print_filename);
+        table.add_row(row);
     }
 
-    println!("{table}");
+    table
 }
 
-/// Extract all selected records
-fn extract_records<R, P, D>(
-    archive: &mut Archive<R>,
+/// Returns the filter selecting the records to extract. Prints the selected records if `verbose` is set.
+fn extraction_filter<P: AsRef<Path>>(
     filter_list: &[P],
-    destination: D,
-    attributes: u8,
     verbose: bool,
-) -> Result<()>
-where
-    R: Read + Seek,
-    P: AsRef<Path>,
-    D: AsRef<Path>,
-{
-    let report =
-        archive.extract_when_best_effort_with_attr(&destination, attributes, |inner_record| {
-            let take = record_matches_filter(inner_record, filter_list);
-            if take && verbose {
-                println!("{}", inner_record.filename().display());
-            }
-            take
-        })?;
+) -> impl Fn(&Record) -> bool + '_ {
+    move |record| {
+        let take = record_matches_filter(record, filter_list);
+        if take && verbose {
+            println!("{}", record.filename().display());
+        }
+        take
+    }
+}
 
+/// Print warnings and skipped records of an extraction
+fn print_report(report: ExtractionReport) {
     for warning in report.warnings {
         println!("{}: {}", warning.record.display(), warning.message);
     }
@@ -285,40 +435,504 @@ where
     for skipped in report.skipped_entries {
         println!("{}: {}", skipped.record.display(), skipped.error);
     }
+}
+
+/// Unpack `pack` compressed files to their name without the `.z` suffix.
+fn unpack_files<P: AsRef<Path>>(filenames: &[P], destination: Option<&Path>) -> Result<()> {
+    for filename in filenames {
+        let filename = filename.as_ref();
+        let unpacked_name = filename
+            .file_name()
+            .and_then(|name| name.to_str())
+            .and_then(|name| name.strip_suffix(".z"))
+            .filter(|name| !name.is_empty())
+            .ok_or_else(|| {
+                io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    format!("{}: Unknown suffix, expected '.z'.", filename.display()),
+                )
+            })?;
+        let destination = destination
+            .or_else(|| filename.parent())
+            .unwrap_or(Path::new("."))
+            .join(unpacked_name);
 
+        let source = File::open(filename)?;
+        let metadata = source.metadata()?;
+        let mut reader = PackReader::new(BufReader::new(source))?;
+        let mut writer = BufWriter::new(File::create(&destination)?);
+        io::copy(&mut reader, &mut writer)?;
+        drop(writer);
+
+        set_file_times(
+            &destination,
+            FileTime::from_last_access_time(&metadata),
+            FileTime::from_last_modification_time(&metadata),
+        )?;
+    }
     Ok(())
 }
 
 fn main() -> Result<()> {
-    let args = Args::parse();
+    let cli = Cli::parse();
+    match cli.command {
+        Some(Command::Unpack { filenames, chdir }) => unpack_files(&filenames, chdir.as_deref()),
+        Some(Command::Carve {
+            filename,
+            chdir,
+            list,
+        }) => carve_archives(&filename, &chdir, list),
+        Some(Command::Info { filename }) => print_info(&filename),
+        Some(Command::Inspect { filename, json }) => inspect_archive(&filename, json),
+        Some(Command::Research { directory, json }) => research_directory(&directory, json),
+        Some(Command::Verify { filename }) => verify_archive(&filename),
+        None => run(cli
+            .args
+            .expect("Archive arguments are required by the parser.")),
+    }
+}
 
-    let reader = File::open(&args.filename)?;
-    if reader.metadata().unwrap().len() > 0xffffffff {
-        return Err(Error::FileToBig);
+/// Find archives embedded in a file and extract each to `destination/archive-<number>`.
+fn carve_archives(filename: &Path, destination: &Path, list: bool) -> Result<()> {
+    let mut reader = open_archive_file(filename)?;
+    let archives = carve::find_archives(&mut reader)?;
+    if archives.is_empty() {
+        return Err(Error::NoArchiveFound);
+    }
+    for (index, carved) in archives.iter().enumerate() {
+        let directory = destination.join(format!("archive-{}", index + 1));
+        println!(
+            "Archive {} at offset {} ({} bytes)",
+            index + 1,
+            carved.offset,
+            carved.len
+        );
+        if list {
+            continue;
+        }
+        let mut archive = carved.open(&mut reader)?;
+        let report = archive.extract_when_best_effort_with_attr(
+            &directory,
+            attribute::ATTRIBUTE_TIMESTAMPS,
+            |_| true,
+        )?;
+        print_report(report);
     }
-    let reader = BufReader::new(reader);
-    let mut archive = Archive::scan(reader)?;
+    Ok(())
+}
 
-    if args.list {
-        print_content(&mut archive, &args.file_list, args.numeric);
-        if args.acl {
-            print_acls(&mut archive, &args.file_list, args.numeric);
+/// Print the metadata of an archive.
+fn print_info(filename: &Path) -> Result<()> {
+    let archive = Archive::scan(open_archive_file(filename)?)?;
+    print!("{}", format_info(&archive.info()));
+    Ok(())
+}
+
+/// Format the metadata of an archive as one line per field.
+fn format_info(info: &ArchiveInfo) -> String {
+    let date_format = "%Y-%m-%d %H:%M:%S UTC";
+    [
+        ("Disk name", info.disk_name.clone()),
+        ("File system", info.filesystem_name.clone()),
+        ("User", info.username.clone()),
+        ("Created", info.current_date.format(date_format).to_string()),
+        (
+            "Started",
+            info.starting_date.format(date_format).to_string(),
+        ),
+        ("Records", info.records.to_string()),
+        ("Files", info.files.to_string()),
+        ("Directories", info.directories.to_string()),
+        ("Symlinks", info.symlinks.to_string()),
+        ("Other", info.others.to_string()),
+        ("Total size", info.total_size.to_string()),
+        ("Stored size", info.stored_size.to_string()),
+    ]
+    .iter()
+    .map(|(name, value)| format!("{:<12} {value}\n", format!("{name}:")))
+    .collect()
+}
+
+/// Print the raw structure of all records of an archive.
+fn inspect_archive(filename: &Path, json: bool) -> Result<()> {
+    let dumps = inspect::inspect(&mut open_archive_file(filename)?)?;
+    if json {
+        let dumps = dumps.iter().map(dump_json).collect::<Vec<_>>();
+        println!(
+            "{}",
+            serde_json::to_string_pretty(&dumps).map_err(io::Error::from)?
+        );
+    } else {
+        dumps
+            .iter()
+            .for_each(|dump| print!("{}", format_dump(dump)));
+    }
+    Ok(())
+}
+
+/// Format a record dump as text with one line per field.
+fn format_dump(dump: &RecordDump) -> String {
+    let mut output = format!(
+        "Record at offset {} ({:#x}): {}\n",
+        dump.offset,
+        dump.offset,
+        dump.name.as_deref().unwrap_or("<no name>")
+    );
+    if let Some(error) = &dump.error {
+        output += &format!("  Rejected: {error}\n");
+    }
+    let format_field = |field: &Field| {
+        format!(
+            "  {:#08x}  {:<17} {:<12} {}\n",
+            field.offset,
+            field.name,
+            field.hex(),
+            field.decoded
+        )
+    };
+    dump.header
+        .iter()
+        .for_each(|field| output += &format_field(field));
+    if dump.name.is_some() && dump.name_padding.is_empty() {
+        output += "  name padding: none\n";
+    } else if dump.name.is_some() {
+        output += &format!(
+            "  name padding: {}\n",
+            inspect::hex_bytes(&dump.name_padding)
+        );
+    }
+    if let Some(symlink) = &dump.symlink {
+        output += &format!("  symlink: {symlink}\n");
+    }
+    dump.acl
+        .iter()
+        .for_each(|field| output += &format_field(field));
+    if !dump.acl_payload.is_empty() {
+        output += &format!("  ACL payload: {}\n", inspect::hex_bytes(&dump.acl_payload));
+    }
+    if let (Some(start), Some(end)) = (dump.payload_start, dump.payload_end) {
+        output += &format!("  payload: {start}..{end} ({} bytes)\n", end - start);
+    }
+    output
+}
+
+/// Convert a record dump to JSON.
+fn dump_json(dump: &RecordDump) -> serde_json::Value {
+    let fields = |fields: &[Field]| {
+        fields
+            .iter()
+            .map(|field| {
+                serde_json::json!({
+                    "name": field.name,
+                    "offset": field.offset,
+                    "hex": field.hex(),
+                    "decoded": field.decoded,
+                })
+            })
+            .collect::<Vec<_>>()
+    };
+    serde_json::json!({
+        "offset": dump.offset,
+        "error": dump.error,
+        "header": fields(&dump.header),
+        "computed_checksum": dump.computed_checksum,
+        "name": dump.name,
+        "name_padding": inspect::hex_bytes(&dump.name_padding),
+        "symlink": dump.symlink,
+        "acl": fields(&dump.acl),
+        "acl_payload": inspect::hex_bytes(&dump.acl_payload),
+        "payload_start": dump.payload_start,
+        "payload_end": dump.payload_end,
+    })
+}
+
+/// Collect statistics of the unknown header fields of all archives in `directory` and print them
+/// as CSV or JSON. Files which can't be read are reported and skipped.
+fn research_directory(directory: &Path, json: bool) -> Result<()> {
+    let mut corpus = Corpus::new();
+    for path in files_in(directory)? {
+        let added = open_archive_file(&path).and_then(|reader| corpus.add_archive(&path, reader));
+        if let Err(error) = added {
+            eprintln!("{}: {error}", path.display());
+        }
+    }
+    eprintln!("{} archives scanned.", corpus.archives());
+    if json {
+        let observations = corpus
+            .observations()
+            .map(observation_json)
+            .collect::<Vec<_>>();
+        let output = serde_json::json!({
+            "archives": corpus.archives(),
+            "observations": observations,
+        });
+        println!(
+            "{}",
+            serde_json::to_string_pretty(&output).map_err(io::Error::from)?
+        );
+    } else {
+        print!("{}", observations_csv(corpus.observations()));
+    }
+    Ok(())
+}
+
+/// Returns all files in `directory` and its subdirectories in order.
+///
+/// Symlinks to directories are skipped, as they may form loops.
+fn files_in(directory: &Path) -> Result<Vec<PathBuf>> {
+    let mut files = vec![];
+    let mut entries = std::fs::read_dir(directory)?
+        .map(|entry| entry.and_then(|entry| Ok((entry.path(), entry.file_type()?))))
+        .collect::<io::Result<Vec<_>>>()?;
+    entries.sort_by(|(a, _), (b, _)| a.cmp(b));
+    for (path, file_type) in entries {
+        if file_type.is_dir() {
+            files.extend(files_in(&path)?);
+        } else if !(file_type.is_symlink() && path.is_dir()) {
+            files.push(path);
+        }
+    }
+    Ok(files)
+}
+
+/// Format an example of an observation as `path@offset`.
+fn format_example(example: &Example) -> String {
+    format!("{}@{}", example.path.display(), example.offset)
+}
+
+/// Format observations as CSV with one row per field, value and context.
+fn observations_csv<'a>(observations: impl Iterator<Item = &'a Observation>) -> String {
+    let quote = |cell: String| {
+        if cell.contains([',', '"', '\n']) {
+            format!("\"{}\"", cell.replace('"', "\"\""))
+        } else {
+            cell
         }
-    } else if args.acl {
-        print_acls(&mut archive, &args.file_list, args.numeric);
+    };
+    let mut output = "field,value,context,count,examples\n".to_string();
+    for observation in observations {
+        let examples = observation
+            .examples
+            .iter()
+            .map(format_example)
+            .collect::<Vec<_>>()
+            .join(" ");
+        output += &format!(
+            "{},{:#010x},{},{},{}\n",
+            observation.field,
+            observation.value,
+            observation.context,
+            observation.count,
+            quote(examples)
+        );
+    }
+    output
+}
+
+/// Convert an observation to JSON.
+fn observation_json(observation: &Observation) -> serde_json::Value {
+    let examples = observation
+        .examples
+        .iter()
+        .map(|example| {
+            serde_json::json!({
+                "path": example.path.display().to_string(),
+                "offset": example.offset,
+            })
+        })
+        .collect::<Vec<_>>();
+    serde_json::json!({
+        "field": observation.field,
+        "value": observation.value,
+        "hex": format!("{:#010x}", observation.value),
+        "context": observation.context,
+        "count": observation.count,
+        "examples": examples,
+    })
+}
+
+/// Check the integrity of an archive and print all problems found.
+fn verify_archive(filename: &Path) -> Result<()> {
+    let mut archive = Archive::recover(open_archive_file(filename)?)?;
+    let diagnostics = archive.validate()?;
+    for diagnostic in &diagnostics {
+        println!("{diagnostic}");
+    }
+    // A missing checksum is reported as warning too.
+    let checksum = if archive.header().stored_checksum() != 0 {
+        "checked"
     } else {
-        extract_records(
-            &mut archive,
-            &args.file_list,
-            args.chdir,
+        "missing"
+    };
+    println!("Header checksum {checksum}.");
+    let errors = diagnostics
+        .iter()
+        .filter(|diagnostic| diagnostic.is_error())
+        .count();
+    println!(
+        "{} records checked, {} error(s), {} warning(s).",
+        archive.records().len(),
+        errors,
+        diagnostics.len() - errors
+    );
+    if errors > 0 {
+        return Err(Error::ValidationFailed(errors));
+    }
+    Ok(())
+}
+
+/// List or extract a BFF archive
+fn run(args: Args) -> Result<()> {
+    if args.filename == Path::new("-") {
+        if args.archive.is_some() || args.mksysb || args.recover {
+            return Err(io::Error::new(
+                io::ErrorKind::Unsupported,
+                "Selecting an archive of an image or recovering needs a seekable file.",
+            )
+            .into());
+        }
+        return run_stream(args, compression::decompress(io::stdin().lock())?);
+    }
+
+    if args.multi_volume {
+        let volumes = std::iter::once(&args.filename)
+            .chain(&args.file_list)
+            .map(open_archive_file)
+            .collect::<Result<Vec<_>>>()?;
+        // The file list names the volumes, so all records are processed.
+        let args = Args {
+            file_list: vec![],
+            ..args
+        };
+        return open_and_process_archive(&args, VolumeReader::new(volumes)?);
+    }
+
+    open_and_process_archive(&args, open_archive_file(&args.filename)?)
+}
+
+/// Open the archive, or the requested archive of a tape image, and list or extract its records
+fn open_and_process_archive<R: Read + Seek>(args: &Args, mut reader: R) -> Result<()> {
+    if args.mksysb {
+        let backup = mksysb::find_backup(&mut reader)?.ok_or(Error::BackupNotFound)?;
+        if args.verbose {
+            println!(
+                "Found {} backup at offset {}.",
+                backup.kind, backup.archive.offset
+            );
+        }
+        return process_tape_archive(args, &backup.archive, reader);
+    }
+    let Some(number) = args.archive else {
+        return process_archive(args, open_archive(args, reader)?);
+    };
+    let mut archives = TapeArchives::new(reader);
+    let tape_archive = archives
+        .nth(number as usize - 1)
+        .ok_or(Error::ArchiveNotFound(number as usize))??;
+    process_tape_archive(args, &tape_archive, archives.into_inner())
+}
+
+/// List or extract an archive found in an image. It is only read again if it is recovered.
+fn process_tape_archive<R: Read + Seek>(
+    args: &Args,
+    tape_archive: &TapeArchive,
+    reader: R,
+) -> Result<()> {
+    if !args.recover {
+        return process_archive(args, tape_archive.open(reader)?);
+    }
+    let reader = OffsetReader::new(reader, tape_archive.offset, Some(tape_archive.len))?;
+    process_archive(args, open_archive(args, reader)?)
+}
+
+/// Scan the archive, or recover it if requested. Data skipped while recovering is reported.
+fn open_archive<R: Read + Seek>(args: &Args, reader: R) -> Result<Archive<R>> {
+    if !args.recover {
+        return Archive::scan(reader);
+    }
+    let archive = Archive::recover(reader)?;
+    for gap in archive.gaps() {
+        eprintln!("{gap}");
+    }
+    Ok(archive)
+}
+
+/// Open an archive file. Compressed files are decompressed to a temporary file.
+fn open_archive_file<P: AsRef<Path>>(path: P) -> Result<BufReader<File>> {
+    let mut reader = BufReader::new(File::open(path)?);
+    match Compression::detect(&mut reader)? {
+        Some(compression) => {
+            // Archive needs seeking, so the decompressed data is spooled to a temporary file.
+            let mut spool = tempfile::tempfile()?;
+            io::copy(&mut compression.decoder(reader)?, &mut spool)?;
+            spool.rewind()?;
+            Ok(BufReader::new(spool))
+        }
+        None => Ok(reader),
+    }
+}
+
+/// List or extract the records of an opened archive
+fn process_archive<R: Read + Seek>(args: &Args, mut archive: Archive<R>) -> Result<()> {
+    if let Some(charset) = args.from_charset {
+        archive.set_charset(charset);
+        print_name_errors(archive.name_errors());
+    }
+    if args.list || args.acl {
+        print_records(args, archive.records());
+    } else {
+        let report = archive.extract_when_best_effort_with_attr(
+            &args.chdir,
+            args.attributes,
+            extraction_filter(&args.file_list, args.verbose),
+        )?;
+        print_report(report);
+    }
+
+    Ok(())
+}
+
+/// List or extract a BFF archive in a single pass reading strictly forward
+fn run_stream<R: Read>(args: Args, reader: R) -> Result<()> {
+    let mut records = RecordIter::scan(ForwardReader::new(reader))?;
+    if let Some(charset) = args.from_charset {
+        records.set_charset(charset);
+    }
+
+    if args.list || args.acl {
+        // Collect the records first, NFS4 ACL texts follow the records they belong to.
+        let records = records.collect_records()?;
+        print_records(&args, &records);
+    } else {
+        let report = records.extract_when_best_effort_with_attr(
+            &args.chdir,
             args.attributes,
-            args.verbose,
+            extraction_filter(&args.file_list, args.verbose),
         )?;
+        print_report(report);
     }
+    print_name_errors(records.name_errors());
 
     Ok(())
 }
 
+/// Print the record names which couldn't be converted from the requested character set
+fn print_name_errors(errors: &[Error]) {
+    for error in errors {
+        eprintln!("{error}");
+    }
+}
+
+/// Print the content and/or the ACLs of the records as requested by `args`
+fn print_records(args: &Args, records: &[Record]) {
+    if args.list {
+        print_content(records, &args.file_list, args.numeric, args.verbose);
+    }
+    if args.acl {
+        print_acls(records, &args.file_list, args.numeric);
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -401,6 +1015,392 @@ mod tests {
         assert_eq!(args.attributes, attribute::ATTRIBUTE_NONE);
     }
 
+    #[test]
+    fn cli_without_subcommand_parses_archive_args() {
+        let cli = Cli::parse_from(["", "-t", "source", "one"]);
+        assert!(cli.command.is_none());
+        let args = cli.args.unwrap();
+        assert_eq!(args.filename, PathBuf::from("source"));
+        assert_eq!(args.file_list, [PathBuf::from("one")]);
+        assert!(args.list);
+    }
+
+    #[test]
+    fn cli_unpack_subcommand() {
+        let cli = Cli::parse_from(["", "unpack", "file.z", "-C", "out"]);
+        assert!(matches!(
+            cli.command,
+            Some(Command::Unpack { filenames, chdir })
+                if filenames == [PathBuf::from("file.z")] && chdir == Some(PathBuf::from("out"))
+        ));
+    }
+
+    #[test]
+    fn carve_extracts_embedded_archives() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let mut blob = vec![0x42; 333];
+        open_bff_file("test.bff").read_to_end(&mut blob).unwrap();
+        blob.extend([0x42; 77]);
+        let mut writer = bfflib::writer::ArchiveWriter::new(vec![]).unwrap();
+        writer
+            .append_file("./embedded.txt", &Default::default(), b"embedded")
+            .unwrap();
+        blob.extend(writer.finish().unwrap());
+        blob.extend([0x42; 11]);
+        let image = temp_dir.path().join("firmware.bin");
+        std::fs::write(&image, &blob).unwrap();
+        let out = temp_dir.path().join("out");
+
+        carve_archives(&image, &out, false).unwrap();
+
+        assert!(out.join("archive-1/backup").is_dir());
+        assert_eq!(
+            std::fs::read_to_string(out.join("archive-2/embedded.txt")).unwrap(),
+            "embedded"
+        );
+        assert!(matches!(
+            carve_archives(
+                &temp_dir.path().join("out/archive-2/embedded.txt"),
+                &out,
+                true
+            ),
+            Err(Error::NoArchiveFound)
+        ));
+    }
+
+    #[test]
+    fn info_formats_archive_metadata() {
+        let archive = Archive::scan(open_bff_file("test.bff")).unwrap();
+
+        let output = format_info(&archive.info());
+
+        assert!(output.starts_with("Disk name:   by name\n"));
+        assert!(output.contains("User:        root\n"));
+        assert!(output.contains("Created:     2024-05-27 10:04:34 UTC\n"));
+        assert!(output.contains("Files:       2\n"));
+        assert!(output.contains("Directories: 2\n"));
+    }
+
+    #[test]
+    fn verbose_listing_shows_kind_links_inode_and_device() {
+        let archive = Archive::scan(open_bff_file("test.bff")).unwrap();
+        let no_filter: &[PathBuf] = &[];
+
+        let table = content_table(archive.records(), no_filter, true, true).to_string();
+        let line = table
+            .lines()
+            .find(|line| line.trim_end().ends_with("backup/file.txt"))
+            .unwrap();
+
+        assert!(table.lines().next().unwrap().contains("Kind"));
+        assert!(line.contains(" file "));
+        assert!(line.contains(" 1 "));
+        assert!(line.contains(" 10,8 "));
+        assert!(
+            !content_table(archive.records(), no_filter, true, false)
+                .to_string()
+                .contains("Kind")
+        );
+    }
+
+    #[test]
+    fn listing_converts_names_from_charset() {
+        let args = Args::parse_from(["", "-t", "--from-charset", "iso8859-1", "source"]);
+        assert_eq!(args.from_charset, Some(Charset::Iso8859_1));
+        assert!(Args::try_parse_from(["", "--from-charset", "EBCDIC", "source"]).is_err());
+
+        let mut writer = bfflib::writer::ArchiveWriter::new(vec![]).unwrap();
+        writer
+            .append_file("./cafe.txt", &Default::default(), b"")
+            .unwrap();
+        let mut data = writer.finish().unwrap();
+        // Turn the name into "café.txt" in ISO8859-1
+        let name = data.windows(8).position(|w| w == b"cafe.txt").unwrap();
+        data[name + 3] = 0xE9;
+        let mut archive = Archive::scan(io::Cursor::new(data)).unwrap();
+        archive.set_charset(args.from_charset.unwrap());
+        let no_filter: &[PathBuf] = &[];
+
+        let table = content_table(archive.records(), no_filter, true, false).to_string();
+
+        assert!(table.contains("./café.txt"));
+        assert!(archive.name_errors().is_empty());
+    }
+
+    #[test]
+    fn inspect_dumps_records_as_text_and_json() {
+        let dumps = inspect::inspect(&mut open_bff_file("test.bff")).unwrap();
+
+        let text = format_dump(&dumps[1]);
+        assert!(text.starts_with("Record at offset 184 (0xb8): backup/file.txt\n"));
+        assert!(text.contains("  0x0000bc  unk04             0x0001076e   checksum 0x076e"));
+        assert!(text.contains("  payload: 304..307 (3 bytes)\n"));
+
+        let json = dump_json(&dumps[1]);
+        assert_eq!(json["name"], "backup/file.txt");
+        assert_eq!(json["header"][2]["name"], "magic");
+        assert_eq!(json["header"][2]["hex"], "0xea6b");
+        assert_eq!(json["error"], serde_json::Value::Null);
+    }
+
+    #[test]
+    fn research_collects_fields_of_all_archives() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let resources = Path::new("../resources/test");
+        std::fs::create_dir(temp_dir.path().join("sub")).unwrap();
+        std::fs::copy(resources.join("test.bff"), temp_dir.path().join("a.bff")).unwrap();
+        std::fs::copy(
+            resources.join("test.bff.gz"),
+            temp_dir.path().join("sub/b.bff.gz"),
+        )
+        .unwrap();
+        std::fs::write(temp_dir.path().join("notes.txt"), "no archive").unwrap();
+
+        let files = files_in(temp_dir.path()).unwrap();
+        assert_eq!(files.len(), 3);
+        let mut corpus = Corpus::new();
+        for path in &files {
+            corpus
+                .add_archive(path, open_archive_file(path).unwrap())
+                .unwrap();
+        }
+        assert_eq!(corpus.archives(), 2);
+
+        let csv = observations_csv(corpus.observations());
+        let row = csv
+            .lines()
+            .find(|line| line.starts_with("FileHeader.unk44,"))
+            .unwrap();
+        assert!(row.starts_with("FileHeader.unk44,0x00000064,archive,2,"));
+        assert!(row.contains("a.bff@68"));
+        assert!(csv.starts_with("field,value,context,count,examples\n"));
+
+        let json = observation_json(corpus.observations().next().unwrap());
+        assert_eq!(json["examples"][0]["offset"], 16);
+        research_directory(temp_dir.path(), true).unwrap();
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn files_in_skips_symlinked_directories() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        std::fs::create_dir(temp_dir.path().join("sub")).unwrap();
+        std::fs::write(temp_dir.path().join("sub/a.bff"), "").unwrap();
+        std::os::unix::fs::symlink("..", temp_dir.path().join("sub/loop")).unwrap();
+        std::os::unix::fs::symlink("a.bff", temp_dir.path().join("sub/b.bff")).unwrap();
+
+        let files = files_in(temp_dir.path()).unwrap();
+        assert_eq!(
+            files,
+            [
+                temp_dir.path().join("sub/a.bff"),
+                temp_dir.path().join("sub/b.bff")
+            ]
+        );
+    }
+
+    #[test]
+    fn verify_fails_on_errors() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let mut writer = bfflib::writer::ArchiveWriter::new(vec![]).unwrap();
+        writer
+            .append_file_compressed("./file.txt", &Default::default(), &b"data ".repeat(20))
+            .unwrap();
+        let mut bytes = writer.finish().unwrap();
+        let archive = temp_dir.path().join("archive.bff");
+        std::fs::write(&archive, &bytes).unwrap();
+        verify_archive(&archive).unwrap();
+
+        // Let the decoded size differ from the size in the record header
+        bytes[96..100].copy_from_slice(&150u32.to_le_bytes());
+        std::fs::write(&archive, &bytes).unwrap();
+        assert!(matches!(
+            verify_archive(&archive),
+            Err(Error::ValidationFailed(1))
+        ));
+    }
+
+    #[test]
+    fn cli_requires_filename_without_subcommand() {
+        assert!(Cli::try_parse_from([""]).is_err());
+    }
+
+    #[test]
+    fn unpack_writes_file_without_suffix() {
+        use bfflib::pack::PackWriter;
+        use std::io::Write;
+
+        let temp_dir = tempfile::tempdir().unwrap();
+        let packed_path = temp_dir.path().join("notes.txt.z");
+        let mut writer = PackWriter::new(File::create(&packed_path).unwrap());
+        writer.write_all(b"unpacked content\n").unwrap();
+        writer.finish().unwrap();
+
+        unpack_files(&[&packed_path], None).unwrap();
+
+        assert_eq!(
+            std::fs::read(temp_dir.path().join("notes.txt")).unwrap(),
+            b"unpacked content\n"
+        );
+        assert!(unpack_files(&[temp_dir.path().join("notes.txt")], None).is_err());
+    }
+
+    #[test]
+    fn run_stream_extracts_from_non_seekable_reader() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let chdir = temp_dir.path().to_string_lossy().to_string();
+        let args = Args::parse_from(["", "-", "-C", &chdir, "-A", "n"]);
+        let mut bytes = vec![];
+        open_bff_file("test.bff").read_to_end(&mut bytes).unwrap();
+
+        // A chained reader can't seek.
+        run_stream(args, io::empty().chain(bytes.as_slice())).unwrap();
+
+        assert_eq!(
+            std::fs::read_to_string(temp_dir.path().join("backup/file.txt")).unwrap(),
+            "abc"
+        );
+    }
+
+    #[test]
+    fn run_extracts_compressed_archives() {
+        for filename in ["test.bff.gz", "test.bff.bz2", "test.bff.Z"] {
+            let temp_dir = tempfile::tempdir().unwrap();
+            let chdir = temp_dir.path().to_string_lossy().to_string();
+            let mut path = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+            path.push("../resources/test");
+            let path = path.join(filename).to_string_lossy().to_string();
+            let args = Args::parse_from(["", &path, "-C", &chdir, "-A", "n"]);
+
+            run(args).unwrap();
+
+            assert_eq!(
+                std::fs::read_to_string(temp_dir.path().join("backup/file.txt")).unwrap(),
+                "abc",
+                "{filename}"
+            );
+        }
+    }
+
+    #[test]
+    fn run_extracts_multi_volume_archive() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let mut bytes = vec![];
+        open_bff_file("test.bff").read_to_end(&mut bytes).unwrap();
+        let split = 200;
+        let first = temp_dir.path().join("test.bff.1");
+        let second = temp_dir.path().join("test.bff.2");
+        std::fs::write(&first, &bytes[..split]).unwrap();
+        // The header of the second volume carries the volume number 2.
+        let mut header = *Archive::new(io::Cursor::new(&bytes)).unwrap().header();
+        header.checksum = 2 << 16;
+        let writer = bfflib::writer::ArchiveWriter::with_header(vec![], header).unwrap();
+        let second_header = writer.finish().unwrap();
+        std::fs::write(&second, [&second_header[..72], &bytes[split..]].concat()).unwrap();
+        let chdir = temp_dir.path().join("out").to_string_lossy().to_string();
+        let args = Args::parse_from([
+            "",
+            &first.to_string_lossy(),
+            &second.to_string_lossy(),
+            "--multi-volume",
+            "-C",
+            &chdir,
+            "-A",
+            "n",
+        ]);
+
+        run(args).unwrap();
+
+        assert_eq!(
+            std::fs::read_to_string(temp_dir.path().join("out/backup/file.txt")).unwrap(),
+            "abc"
+        );
+    }
+
+    #[test]
+    fn run_extracts_archive_of_tape_image() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let mut bytes = vec![];
+        open_bff_file("test.bff").read_to_end(&mut bytes).unwrap();
+        let mut writer = bfflib::writer::ArchiveWriter::new(vec![]).unwrap();
+        writer
+            .append_file("second.txt", &Default::default(), b"second")
+            .unwrap();
+        bytes.extend(writer.finish().unwrap());
+        let image = temp_dir.path().join("tape.img");
+        std::fs::write(&image, &bytes).unwrap();
+        let chdir = temp_dir.path().join("out").to_string_lossy().to_string();
+        let image = image.to_string_lossy().to_string();
+
+        let args = Args::parse_from(["", &image, "--archive", "2", "-C", &chdir, "-A", "n"]);
+        run(args).unwrap();
+        assert_eq!(
+            std::fs::read_to_string(temp_dir.path().join("out/second.txt")).unwrap(),
+            "second"
+        );
+        assert!(!temp_dir.path().join("out/backup").exists());
+
+        let args = Args::parse_from(["", &image, "--archive", "3", "-t"]);
+        assert!(matches!(run(args), Err(Error::ArchiveNotFound(3))));
+    }
+
+    #[test]
+    fn run_recovers_records_behind_damaged_record() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let mut writer = bfflib::writer::ArchiveWriter::new(vec![]).unwrap();
+        for name in ["./damaged.txt", "./intact.txt"] {
+            writer
+                .append_file(name, &Default::default(), name.as_bytes())
+                .unwrap();
+        }
+        let mut bytes = writer.finish().unwrap();
+        // Overwrite the magic number of the first record
+        bytes[74..76].fill(0);
+        let archive = temp_dir.path().join("damaged.bff");
+        std::fs::write(&archive, &bytes).unwrap();
+        let chdir = temp_dir.path().join("out").to_string_lossy().to_string();
+        let archive = archive.to_string_lossy().to_string();
+
+        let args = Args::parse_from(["", &archive, "--recover", "-C", &chdir, "-A", "n"]);
+        run(args).unwrap();
+        assert!(!temp_dir.path().join("out/damaged.txt").exists());
+        assert_eq!(
+            std::fs::read_to_string(temp_dir.path().join("out/intact.txt")).unwrap(),
+            "./intact.txt"
+        );
+    }
+
+    #[test]
+    fn run_extracts_backup_of_mksysb_image() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let mut bytes = vec![0xAA; 1024];
+        let mut writer = bfflib::writer::ArchiveWriter::new(vec![]).unwrap();
+        writer
+            .append_file("./image.data", &Default::default(), b"image.data")
+            .unwrap();
+        writer
+            .append_file("./etc/motd", &Default::default(), b"welcome")
+            .unwrap();
+        bytes.extend(writer.finish().unwrap());
+        let image = temp_dir.path().join("rootvg.mksysb");
+        std::fs::write(&image, &bytes).unwrap();
+        let chdir = temp_dir.path().join("out").to_string_lossy().to_string();
+        let image = image.to_string_lossy().to_string();
+
+        let args = Args::parse_from(["", &image, "--mksysb", "-C", &chdir, "-A", "n"]);
+        run(args).unwrap();
+        assert_eq!(
+            std::fs::read_to_string(temp_dir.path().join("out/etc/motd")).unwrap(),
+            "welcome"
+        );
+
+        let mut plain = vec![];
+        open_bff_file("test.bff").read_to_end(&mut plain).unwrap();
+        std::fs::write(&image, &plain).unwrap();
+        let args = Args::parse_from(["", &image, "--mksysb", "-t"]);
+        assert!(matches!(run(args), Err(Error::BackupNotFound)));
+    }
+
     #[test]
     fn acl_mixed_sample_formats_aixc_acl_as_aixc() {
         let archive = Archive::scan(open_bff_file("acl_aixc_nfs4.bff")).unwrap();
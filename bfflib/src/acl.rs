@@ -278,7 +278,7 @@ pub(crate) fn parse_acl_payload(buf: &[u8], num_entries: u32) -> (u16, u16, u16,
     let everyone_perm = u16::from_le_bytes([buf[6], buf[7]]);
 
     let ext_count = (num_entries as usize).saturating_sub(3);
-    let mut entries = Vec::with_capacity(ext_count);
+    let mut entries = Vec::with_capacity(ext_count.min(buf.len() / 12));
     let mut pos = 8usize;
 
     for _ in 0..ext_count {
@@ -315,7 +315,7 @@ pub(crate) fn parse_nfs4_acl_payload(buf: &[u8], num_entries: u32) -> Vec<Nfs4Ac
     const WHO_OWNER_OR_GROUP: u32 = 0xFFFF_FFFF;
     const WHO_EVERYONE: u32 = 0xFFFF_FFFE;
 
-    let mut entries = Vec::with_capacity(num_entries as usize);
+    let mut entries = Vec::with_capacity((num_entries as usize).min(buf.len() / ACE_SIZE));
 
     for index in 0..num_entries as usize {
         let offset = index * ACE_SIZE;
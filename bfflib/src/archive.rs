@@ -1,12 +1,13 @@
 //! Reading an BFF archive
 
 use std::{
+    fmt::Display,
     io::{self, Read, Seek, SeekFrom},
     path::{Component, Path, PathBuf},
 };
 
 use chrono::{DateTime, NaiveDateTime, Utc};
-use file_mode::Mode;
+use file_mode::{FileType, Mode};
 use normalize_path::NormalizePath;
 
 use crate::{Error, Result};
@@ -17,30 +18,65 @@ use crate::{
     },
     attribute,
     bff::{
-        FILE_MAGIC, FileHeader, HEADER_MAGICS, RecordHeader, compute_file_header_checksum,
-        read_aligned_string,
+        DeviceId, FILE_MAGIC, FileHeader, HEADER_MAGICS, HUFFMAN_MAGIC, OFFSET_FORMAT_MARKER,
+        OffsetRecord, RECORD_FORMAT_MARKER, RecordHeader, SHORT_HEADER_LEN,
+        compute_file_header_checksum, read_aligned_name,
     },
+    charset::Charset,
     extract::{
         ArchiveSource, ExtractionDisposition, extract_record_best_effort_with_attr,
-        extract_record_with_attr,
+        extract_record_with_attr, open_record_reader,
     },
+    info::ArchiveInfo,
+    stream::read_up_to,
+    util::path_from_bytes,
+    validate::{self, Diagnostic, Issue},
+    volume::VolumeReader,
 };
 
 pub use crate::acl::{
     AclData, AclEntry, AclMetadata, AclPrincipalType, AixcAcl, AixcPermissions, Nfs4Acl,
     Nfs4AclEntry, Nfs4AclPrincipal,
 };
-pub use crate::extract::RecordReader;
 pub use crate::extract::{ExtractedEntry, ExtractionReport, ExtractionWarning, SkippedEntry};
+pub use crate::extract::{RecordData, RecordReader};
 
 #[derive(Clone, Copy)]
 enum RecordScanMode {
     Strict,
     BestEffort,
+    /// Like best-effort, but searches the next record after a malformed one.
+    Recover,
+}
+
+/// Longest record name accepted while searching the next record in recovery mode.
+const MAX_RECOVERY_NAME_LEN: usize = 1024;
+/// Size of the blocks read while searching the next record in recovery mode.
+const RECOVERY_BUFFER_SIZE: usize = 64 * 1024;
+
+/// A range of unreadable data skipped in recovery mode.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RecoveryGap {
+    /// Position of the malformed record, or of the payload of a record with a corrupt size
+    pub start: u64,
+    /// Position of the next record found, or the end of the archive
+    pub end: u64,
+}
+
+impl Display for RecoveryGap {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "Skipped {} bytes of unreadable data from offset {} to {}.",
+            self.end - self.start,
+            self.start,
+            self.end
+        )
+    }
 }
 
 /// Read BFF [FileHeader] from the reader
-fn read_file_header<R: Read>(reader: &mut R) -> Result<FileHeader> {
+pub(crate) fn read_file_header<R: Read>(reader: &mut R) -> Result<FileHeader> {
     let mut header_bytes = [0u8; std::mem::size_of::<FileHeader>()];
     reader.read_exact(&mut header_bytes)?;
     let file_header =
@@ -61,6 +97,9 @@ fn read_file_header<R: Read>(reader: &mut R) -> Result<FileHeader> {
             });
         }
     }
+    if file_header.is_by_inode() {
+        return Err(Error::UnsupportedByInodeBackup);
+    }
 
     Ok(file_header)
 }
@@ -74,29 +113,45 @@ fn align_reader_to_eight<R: Seek>(reader: &mut R) -> Result<()> {
     Ok(())
 }
 
-fn is_offset_record(record_header: &RecordHeader) -> bool {
-    record_header.format_marker() == 0x07
+/// A record read from an archive.
+enum Entry {
+    File(Box<Record>),
+    Offset(OffsetRecord),
+    /// The data ended before the record
+    End,
 }
 
-/// Read the next [Record] from the reader.
-fn read_next_record<R: Read + Seek>(reader: &mut R) -> Result<Option<Record>> {
+/// Read a [Record] up to the start of its payload or an [OffsetRecord]. The reader is left at
+/// the payload of the record or behind the offset record.
+fn read_record_head<R: Read + Seek>(reader: &mut R) -> Result<Entry> {
+    let header_position = reader.stream_position()?;
     let mut header_bytes = [0u8; std::mem::size_of::<RecordHeader>()];
-    reader.read_exact(&mut header_bytes)?;
-    let record_header: RecordHeader =
-        unsafe { std::ptr::read_unaligned(header_bytes.as_ptr().cast()) };
-    if !matches!(record_header.format_marker(), 0x0b | 0x07) {
+    match read_up_to(reader, &mut header_bytes[..SHORT_HEADER_LEN])? {
+        0 => return Ok(Entry::End),
+        SHORT_HEADER_LEN => {}
+        _ => return Err(io::Error::from(io::ErrorKind::UnexpectedEof).into()),
+    }
+    let format_marker = header_bytes[1];
+    if !matches!(format_marker, RECORD_FORMAT_MARKER | OFFSET_FORMAT_MARKER) {
         return Err(Error::InvalidRecord);
     }
-    let magic = record_header.magic;
+    let magic = u16::from_le_bytes([header_bytes[2], header_bytes[3]]);
     if !HEADER_MAGICS.contains(&magic) {
-        return Err(Error::InvalidRecordMagic(record_header.magic));
+        return Err(Error::InvalidRecordMagic(magic));
     }
-    let filename = read_aligned_string(reader)?;
+    if format_marker == OFFSET_FORMAT_MARKER {
+        return read_offset_record(reader, header_position, &header_bytes[..SHORT_HEADER_LEN])
+            .map(Entry::Offset);
+    }
+    reader.read_exact(&mut header_bytes[SHORT_HEADER_LEN..])?;
+    let record_header: RecordHeader =
+        unsafe { std::ptr::read_unaligned(header_bytes.as_ptr().cast()) };
+    let filename = read_aligned_name(reader)?;
 
     // Record is a symlink and we need to read the symlink target too
     let mut symlink = None;
     if record_header.mode & 0xF000 == 0xA000 {
-        symlink = Some(read_aligned_string(reader)?);
+        symlink = Some(read_aligned_name(reader)?);
     }
 
     let mut trailer_bytes = [0u8; std::mem::size_of::<RecordAcl>()];
@@ -115,10 +170,11 @@ fn read_next_record<R: Read + Seek>(reader: &mut R) -> Result<Option<Record>> {
             let inline = &record_trailer.acl_payload_bytes[..acl_len.min(TRAILER_INLINE_ACL_BYTES)];
             let mut payload = inline.to_vec();
             if acl_len > TRAILER_INLINE_ACL_BYTES {
-                let extra = acl_len - TRAILER_INLINE_ACL_BYTES;
-                let mut buf = vec![0u8; extra];
-                reader.read_exact(&mut buf)?;
-                payload.extend_from_slice(&buf);
+                // Don't trust the length for allocation, a corrupt record would exhaust memory.
+                let extra = (acl_len - TRAILER_INLINE_ACL_BYTES) as u64;
+                if reader.take(extra).read_to_end(&mut payload)? as u64 != extra {
+                    return Err(io::Error::from(io::ErrorKind::UnexpectedEof).into());
+                }
             }
             Some(payload)
         } else {
@@ -126,7 +182,7 @@ fn read_next_record<R: Read + Seek>(reader: &mut R) -> Result<Option<Record>> {
         };
 
     if record_header.mode & S_IXACL > 0 && record_trailer.acl_len > 0 {
-        let acl_len = record_trailer.acl_len as usize;
+        let acl_len = u64::from(record_trailer.acl_len);
         let padded_acl_len = (acl_len + 15) & !15;
         let acl_padding = padded_acl_len.saturating_sub(acl_len);
         if acl_padding > 0 {
@@ -139,54 +195,515 @@ fn read_next_record<R: Read + Seek>(reader: &mut R) -> Result<Option<Record>> {
     align_reader_to_eight(reader)?;
 
     let position = reader.stream_position()?;
-    if record_header.size > 0 {
-        reader.seek(SeekFrom::Current(record_header.compressed_size as i64))?;
-    }
-    let aligned_up = (record_header.compressed_size + 7) & !7;
-    reader.seek(SeekFrom::Current(
-        (aligned_up - record_header.compressed_size) as i64,
-    ))?;
 
     let record = Record::new(
         record_header,
         record_trailer,
         acl_payload,
-        PathBuf::from(filename),
-        symlink.map(PathBuf::from),
-        position as u32,
+        filename,
+        symlink,
+        header_position,
+        position,
     );
+    // Corrupt sizes must not move the next record beyond the addressable range.
+    stored_payload_len(&record)?
+        .checked_add(position)
+        .ok_or(Error::InvalidRecord)?;
+    Ok(Entry::File(Box::new(record)))
+}
+
+/// Read the rest of an [OffsetRecord] at `position` following its common `header`.
+fn read_offset_record<R: Read>(
+    reader: &mut R,
+    position: u64,
+    header: &[u8],
+) -> Result<OffsetRecord> {
+    // The length is stored in 8-byte units and includes the common header.
+    let len = usize::from(header[0]).max(1) * 8;
+    let mut bytes = header.to_vec();
+    bytes.resize(len, 0);
+    reader.read_exact(&mut bytes[SHORT_HEADER_LEN..])?;
+    Ok(OffsetRecord::parse(position, &bytes))
+}
+
+/// Returns `true` if the name of `record` is non-empty and free of control characters.
+fn has_plausible_name(record: &Record) -> bool {
+    let name = record.filename().to_string_lossy();
+    !name.is_empty() && !name.chars().any(char::is_control)
+}
+
+/// Returns the amount of bytes the payload of `record` occupies in the archive including padding.
+///
+/// Returns [Error::InvalidRecord] if the padded size overflows.
+fn stored_payload_len(record: &Record) -> Result<u64> {
+    let compressed_size = record.compressed_size();
+    let padded_len = compressed_size
+        .checked_next_multiple_of(8)
+        .ok_or(Error::InvalidRecord)?;
+    if record.size() > 0 {
+        Ok(padded_len)
+    } else {
+        Ok(padded_len - compressed_size)
+    }
+}
+
+/// Read the next [Record] from the reader and skip its payload.
+///
+/// Returns [None] at an [OffsetRecord].
+pub(crate) fn read_next_record<R: Read + Seek>(reader: &mut R) -> Result<Option<Record>> {
+    match read_record_head(reader)? {
+        Entry::File(record) => {
+            let payload_len =
+                i64::try_from(stored_payload_len(&record)?).map_err(|_| Error::InvalidRecord)?;
+            reader.seek(SeekFrom::Current(payload_len))?;
+            Ok(Some(*record))
+        }
+        Entry::Offset(_) => Ok(None),
+        Entry::End => Err(io::Error::from(io::ErrorKind::UnexpectedEof).into()),
+    }
+}
+
+/// Parses the records of an archive one by one.
+///
+/// The payload of a record is skipped when the next record is requested, so it can be read in
+/// between. Only seeking forward is needed.
+struct RecordScanner {
+    mode: RecordScanMode,
+    /// Position of the record following the record returned last
+    next_record_pos: Option<u64>,
+    /// Position behind the offset record ending the archive, once it was read
+    end_pos: Option<u64>,
+    /// Position of the record read last
+    record_pos: Option<u64>,
+    /// Position of the payload of the file record returned last
+    payload_pos: Option<u64>,
+    /// Position of the record cut off by the end of the data
+    truncated: Option<u64>,
+    /// Data skipped in recovery mode
+    gaps: Vec<RecoveryGap>,
+    /// Offset records read so far
+    offset_records: Vec<OffsetRecord>,
+    finished: bool,
+}
+
+impl RecordScanner {
+    fn new(mode: RecordScanMode) -> Self {
+        Self {
+            mode,
+            next_record_pos: None,
+            end_pos: None,
+            record_pos: None,
+            payload_pos: None,
+            truncated: None,
+            gaps: vec![],
+            offset_records: vec![],
+            finished: false,
+        }
+    }
+
+    /// Read the next [Record] from the reader and skip malformed records in best-effort mode.
+    ///
+    /// Returns [None] at the end of the archive.
+    fn next_record<R: Read + Seek>(&mut self, reader: &mut R) -> Option<Result<Record>> {
+        if self.finished {
+            return None;
+        }
+        let result = self.read_record(reader).transpose();
+        if !matches!(result, Some(Ok(_))) {
+            self.finished = true;
+        }
+        result
+    }
+
+    fn read_record<R: Read + Seek>(&mut self, reader: &mut R) -> Result<Option<Record>> {
+        loop {
+            let result = self.skip_payload(reader).and_then(|()| {
+                self.record_pos = Some(reader.stream_position()?);
+                read_record_head(reader)
+            });
+            // The data behind an offset record is padding, usually stale copies of earlier records.
+            // Only recovery mode continues behind it if a valid record follows.
+            let after_offset_record = self.end_pos.is_some();
+            match result {
+                Ok(Entry::Offset(offset_record)) => {
+                    self.end_pos = Some(offset_record.end());
+                    self.offset_records.push(offset_record);
+                    if !matches!(self.mode, RecordScanMode::Recover) {
+                        return Ok(None);
+                    }
+                }
+                Ok(Entry::File(record)) if after_offset_record && !has_plausible_name(&record) => {
+                    return Ok(None);
+                }
+                Ok(Entry::File(record)) => {
+                    self.end_pos = None;
+                    let payload_len = stored_payload_len(&record)?;
+                    self.payload_pos = Some(record.file_position());
+                    self.next_record_pos = Some(
+                        reader
+                            .stream_position()?
+                            .checked_add(payload_len)
+                            .ok_or(Error::InvalidRecord)?,
+                    );
+                    return Ok(Some(*record));
+                }
+                Ok(Entry::End) if after_offset_record => return Ok(None),
+                // A corrupt payload size of the previous record may point behind the end.
+                Ok(Entry::End) if matches!(self.mode, RecordScanMode::Recover) => {
+                    let end = reader.seek(SeekFrom::End(0))?;
+                    let beyond_end = self.record_pos.is_some_and(|position| position > end);
+                    if !beyond_end || !self.resynchronize_behind_payload(reader)? {
+                        return Ok(None);
+                    }
+                }
+                Ok(Entry::End) => return Ok(None),
+                Err(_) if after_offset_record => return Ok(None),
+                Err(Error::IoError(io_e))
+                    if io_e.kind() == io::ErrorKind::UnexpectedEof
+                        && matches!(self.mode, RecordScanMode::Recover) =>
+                {
+                    if !self.resynchronize_behind_payload(reader)? {
+                        self.truncated = self.record_pos;
+                        return Ok(None);
+                    }
+                }
+                Err(Error::IoError(io_e)) if io_e.kind() == io::ErrorKind::UnexpectedEof => {
+                    self.truncated = self.record_pos;
+                    return Ok(None);
+                }
+                Err(Error::InvalidRecord | Error::InvalidRecordMagic(_))
+                    if matches!(self.mode, RecordScanMode::Recover) =>
+                {
+                    if !self.resynchronize(reader)? {
+                        return Ok(None);
+                    }
+                }
+                Err(Error::InvalidRecord) if matches!(self.mode, RecordScanMode::BestEffort) => {}
+                Err(Error::InvalidRecordMagic(_))
+                    if matches!(self.mode, RecordScanMode::BestEffort) => {}
+                Err(e) => return Err(e),
+            }
+        }
+    }
+
+    /// Search the next plausible record behind the malformed record read last and position the
+    /// reader there. The skipped data is recorded as [RecoveryGap].
+    ///
+    /// Returns `false` if no record was found up to the end of the reader.
+    fn resynchronize<R: Read + Seek>(&mut self, reader: &mut R) -> Result<bool> {
+        let Some(start) = self.record_pos else {
+            return Ok(false);
+        };
+        let next = find_record_header(reader, start + 8)?;
+        let end = match next {
+            Some(position) => position,
+            None => reader.seek(SeekFrom::End(0))?,
+        };
+        self.gaps.push(RecoveryGap { start, end });
+        self.next_record_pos = next;
+        Ok(next.is_some())
+    }
+
+    /// Search the next plausible record behind the start of the payload of the record returned
+    /// last, whose size may be corrupt, after the data ended at the record read last.
+    ///
+    /// Returns `false` if no other record was found.
+    fn resynchronize_behind_payload<R: Read + Seek>(&mut self, reader: &mut R) -> Result<bool> {
+        let (Some(start), Some(failed)) = (self.payload_pos.take(), self.record_pos) else {
+            return Ok(false);
+        };
+        match find_record_header(reader, start)? {
+            // A record cut off by the end of the data may be found again.
+            Some(next) if next != failed => {
+                self.gaps.push(RecoveryGap { start, end: next });
+                self.next_record_pos = Some(next);
+                Ok(true)
+            }
+            _ => Ok(false),
+        }
+    }
+
+    fn skip_payload<R: Seek>(&mut self, reader: &mut R) -> Result<()> {
+        if let Some(position) = self.next_record_pos.take() {
+            reader.seek(SeekFrom::Start(position))?;
+        }
+        Ok(())
+    }
+}
 
-    if is_offset_record(record.header()) {
-        return Ok(None);
+/// Find the next plausible record header on an 8-byte boundary at or behind `position`.
+pub(crate) fn find_record_header<R: Read + Seek>(
+    reader: &mut R,
+    position: u64,
+) -> Result<Option<u64>> {
+    let header_len = std::mem::size_of::<RecordHeader>();
+    let window = header_len + MAX_RECOVERY_NAME_LEN;
+    let mut buf = vec![0u8; RECOVERY_BUFFER_SIZE];
+    let mut start = position.next_multiple_of(8);
+    loop {
+        reader.seek(SeekFrom::Start(start))?;
+        let len = read_up_to(reader, &mut buf)?;
+        let at_end = len < buf.len();
+        let mut offset = 0;
+        // Candidates near the end of the block are checked in the next block with their name.
+        while offset + header_len < len && (at_end || offset + window <= len) {
+            if is_plausible_record(&buf[offset..len]) {
+                return Ok(Some(start + offset as u64));
+            }
+            offset += 8;
+        }
+        if at_end {
+            return Ok(None);
+        }
+        start += offset as u64;
     }
+}
 
-    Ok(Some(record))
+/// Returns `true` if `data` starts with a plausible record header.
+///
+/// The format marker and magic number have to be valid. Apart from end of archive markers, the
+/// header has to be followed by a non-empty name without control characters.
+fn is_plausible_record(data: &[u8]) -> bool {
+    let header_len = std::mem::size_of::<RecordHeader>();
+    let magic = u16::from_le_bytes([data[2], data[3]]);
+    if !matches!(data[1], RECORD_FORMAT_MARKER | OFFSET_FORMAT_MARKER)
+        || !HEADER_MAGICS.contains(&magic)
+    {
+        return false;
+    }
+    if data[1] == OFFSET_FORMAT_MARKER {
+        return true;
+    }
+    let name = &data[header_len..data.len().min(header_len + MAX_RECOVERY_NAME_LEN)];
+    match name.iter().position(|&byte| byte == 0) {
+        Some(len) => len > 0 && name[..len].iter().all(|&byte| byte >= 0x20 && byte != 0x7F),
+        None => false,
+    }
 }
 
 /// Read all [Record]s from the reader.
+#[cfg(test)]
 fn read_records<R: Read + Seek>(reader: &mut R, mode: RecordScanMode) -> Result<Vec<Record>> {
-    let mut records = vec![];
-    loop {
-        match read_next_record(reader) {
-            Ok(Some(record)) => records.push(record),
-            Ok(None) => break,
-            Err(e) => match e {
-                // Hopefully not unexpected EOF
-                Error::IoError(io_e) if io_e.kind() == io::ErrorKind::UnexpectedEof => break,
-                Error::InvalidRecord if matches!(mode, RecordScanMode::BestEffort) => {}
-                Error::InvalidRecordMagic(_) if matches!(mode, RecordScanMode::BestEffort) => {}
-                _ => return Err(e),
-            },
+    let mut scanner = RecordScanner::new(mode);
+    std::iter::from_fn(|| scanner.next_record(reader)).collect()
+}
+
+/// An archive read by [read_archive_extent].
+pub(crate) struct ArchiveExtent {
+    pub(crate) header: FileHeader,
+    pub(crate) records: Vec<Record>,
+    pub(crate) offset_records: Vec<OffsetRecord>,
+    /// Position behind the end of the archive
+    pub(crate) len: u64,
+}
+
+/// Read the archive at the current position of the reader in best-effort mode.
+///
+/// Archives without end of archive record end at the end of the reader.
+pub(crate) fn read_archive_extent<R: Read + Seek>(reader: &mut R) -> Result<ArchiveExtent> {
+    let mut iter = RecordIter::scan(reader)?;
+    let records = iter.collect_records()?;
+    let len = match iter.scanner.end_pos {
+        Some(end) => end,
+        None => iter.reader.seek(SeekFrom::End(0))?,
+    };
+    Ok(ArchiveExtent {
+        header: iter.header,
+        records,
+        offset_records: iter.scanner.offset_records,
+        len,
+    })
+}
+
+/// A lazy iterator over the [Record]s of a BFF archive.
+///
+/// Each record is parsed when it is requested, so processing can start before the whole archive
+/// was read. The payload of the record returned last can be read by [RecordIter::payload] and is
+/// skipped otherwise. Use [Archive] for random access by filename.
+///
+/// Records are read strictly forward, so a [ForwardReader](crate::stream::ForwardReader) allows
+/// iterating archives read from pipes.
+///
+/// NFS4 ACL texts are stored in separate records behind the records they belong to, so the
+/// records returned by [Iterator::next] carry no NFS4 ACL text. Use [RecordIter::collect_records]
+/// to get the records with their texts attached.
+pub struct RecordIter<R> {
+    reader: R,
+    header: FileHeader,
+    records_start_pos: u64,
+    scanner: RecordScanner,
+    /// The record returned last
+    current: Option<Record>,
+    /// Character set to convert names from
+    charset: Option<Charset>,
+    name_errors: Vec<Error>,
+}
+
+impl<R: Read + Seek> RecordIter<R> {
+    /// Creates a new RecordIter instance using strict record parsing.
+    pub fn new(reader: R) -> Result<Self> {
+        Self::from_reader_with_mode(reader, RecordScanMode::Strict)
+    }
+
+    /// Creates a new RecordIter instance using best-effort scanning.
+    pub fn scan(reader: R) -> Result<Self> {
+        Self::from_reader_with_mode(reader, RecordScanMode::BestEffort)
+    }
+
+    fn from_reader_with_mode(mut reader: R, mode: RecordScanMode) -> Result<Self> {
+        let header = read_file_header(&mut reader)?;
+        let records_start_pos = reader.stream_position()?;
+        Ok(Self {
+            reader,
+            header,
+            records_start_pos,
+            scanner: RecordScanner::new(mode),
+            current: None,
+            charset: None,
+            name_errors: vec![],
+        })
+    }
+
+    /// Returns the [FileHeader] of the archive
+    pub fn header(&self) -> &FileHeader {
+        &self.header
+    }
+
+    /// Returns the offset records read so far.
+    pub fn offset_records(&self) -> &[OffsetRecord] {
+        &self.scanner.offset_records
+    }
+
+    /// Convert the names of the following records from `charset` to UTF-8. See
+    /// [Record::transcode].
+    pub fn set_charset(&mut self, charset: Charset) {
+        self.charset = Some(charset);
+    }
+
+    /// Returns the names read so far which couldn't be converted from the character set.
+    pub fn name_errors(&self) -> &[Error] {
+        &self.name_errors
+    }
+
+    /// Returns the position of the first record in the BFF file
+    pub fn records_start_pos(&self) -> u64 {
+        self.records_start_pos
+    }
+
+    /// Creates a reader for the payload of the record returned last.
+    ///
+    /// Returns [None] if no record was returned yet or the iteration has ended.
+    pub fn payload(&mut self) -> Result<Option<RecordReader<'_>>> {
+        self.open_payload(false)
+    }
+
+    /// Creates a raw reader for the payload of the record returned last without decoding.
+    pub fn raw_payload(&mut self) -> Result<Option<RecordReader<'_>>> {
+        self.open_payload(true)
+    }
+
+    fn open_payload(&mut self, raw: bool) -> Result<Option<RecordReader<'_>>> {
+        let Some(record) = &self.current else {
+            return Ok(None);
+        };
+        open_record_reader(&mut self.reader, record, raw)
+    }
+
+    /// Extract the remaining records to a target directory and filter them by a callback function
+    /// and set file modes to be extracted.
+    ///
+    /// `when` is a callback function returning `true` to extract the record or `false` to skip the record.
+    pub fn extract_when_with_attr<P, C>(
+        &mut self,
+        destination: P,
+        attributes: u8,
+        when: C,
+    ) -> Result<()>
+    where
+        P: AsRef<Path>,
+        C: Fn(&Record) -> bool,
+    {
+        while let Some(record) = self.next() {
+            let record = record?;
+            if when(&record) {
+                let target_path =
+                    validated_extraction_path(destination.as_ref(), record.filename())?.normalize();
+                let mut source = ArchiveSource::new(&mut self.reader);
+                extract_record_with_attr(&mut source, &record, &target_path, attributes)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Extract the remaining records in best-effort mode and return a report.
+    pub fn extract_when_best_effort_with_attr<P, C>(
+        &mut self,
+        destination: P,
+        attributes: u8,
+        when: C,
+    ) -> Result<ExtractionReport>
+    where
+        P: AsRef<Path>,
+        C: Fn(&Record) -> bool,
+    {
+        let mut report = ExtractionReport::default();
+        while let Some(record) = self.next() {
+            let record = record?;
+            if when(&record) {
+                let mut source = ArchiveSource::new(&mut self.reader);
+                extract_record_into_report(
+                    &mut source,
+                    &record,
+                    destination.as_ref(),
+                    attributes,
+                    &mut report,
+                );
+            }
         }
+        Ok(report)
+    }
+
+    /// Collects the remaining records.
+    ///
+    /// Unlike collecting the iterator, the NFS4 ACL texts stored in separate records are read and
+    /// attached to the records they belong to, like [Archive] does.
+    pub fn collect_records(&mut self) -> Result<Vec<Record>> {
+        let mut records = vec![];
+        let mut acl_texts = Nfs4AclTexts::default();
+        while let Some(record) = self.next() {
+            records.push(record?);
+            let mut source = ArchiveSource::new(&mut self.reader);
+            acl_texts.add(&mut records, |record| source.read_text(record))?;
+        }
+        Ok(records)
+    }
+
+    /// Returns the underlying reader.
+    pub fn into_inner(self) -> R {
+        self.reader
+    }
+}
+
+impl<R: Read + Seek> Iterator for RecordIter<R> {
+    type Item = Result<Record>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let mut item = self.scanner.next_record(&mut self.reader);
+        if let (Some(Ok(record)), Some(charset)) = (&mut item, self.charset)
+            && let Err(error) = record.transcode(charset)
+        {
+            self.name_errors.push(error);
+        }
+        self.current = match &item {
+            Some(Ok(record)) => Some(record.clone()),
+            _ => None,
+        };
+        item
     }
-    Ok(records)
 }
 
 /// Find a [Record] by its filename
-fn record_by_filename<'a, P: AsRef<Path>>(
-    records: &'a [Record],
-    filename: P,
-) -> Option<&'a Record> {
+fn record_by_filename<P: AsRef<Path>>(records: &[Record], filename: P) -> Option<&Record> {
     records
         .iter()
         .find(|record| record.filename() == filename.as_ref())
@@ -221,11 +738,19 @@ fn validated_extraction_path<P: AsRef<Path>, Q: AsRef<Path>>(
 }
 
 /// A BFF archive
+///
+/// All records are read by a [RecordIter] when the archive is opened and kept as index, so they
+/// can be accessed by filename and extracted in any order.
 pub struct Archive<R> {
     source: ArchiveSource<R>,
     header: FileHeader,
     records_start_pos: u64,
     records: Vec<Record>,
+    gaps: Vec<RecoveryGap>,
+    offset_records: Vec<OffsetRecord>,
+    /// Position of the record cut off by the end of the data
+    truncated: Option<u64>,
+    name_errors: Vec<Error>,
 }
 
 impl<R: Read + Seek> Archive<R> {
@@ -244,20 +769,67 @@ impl<R: Read + Seek> Archive<R> {
         Self::from_reader_with_mode(reader, RecordScanMode::BestEffort)
     }
 
-    fn from_reader_with_mode(mut reader: R, mode: RecordScanMode) -> Result<Self> {
-        let header = read_file_header(&mut reader)?;
-        let records_start_pos = reader.stream_position()?;
-        let mut records = read_records(&mut reader, mode)?;
-        attach_nfs4_acl_texts(&mut reader, &mut records)?;
+    /// Creates a new Archive instance in recovery mode.
+    ///
+    /// Like [Archive::scan], but after a malformed record the data is searched on 8-byte
+    /// boundaries for the next plausible record header, so the records behind a damaged part of
+    /// the archive are found. The skipped data is reported by [Archive::gaps]. Records directly
+    /// following an [OffsetRecord] are read too, although they may be stale copies of earlier
+    /// records. [Archive::validate] reports them.
+    pub fn recover(reader: R) -> Result<Self> {
+        Self::from_reader_with_mode(reader, RecordScanMode::Recover)
+    }
+
+    /// Creates a new Archive instance from volumes in order using strict record parsing.
+    ///
+    /// The headers of all volumes are validated. See [VolumeReader] for details.
+    pub fn open_volumes(volumes: Vec<R>) -> Result<Archive<VolumeReader<R>>> {
+        Archive::new(VolumeReader::new(volumes)?)
+    }
+
+    fn from_reader_with_mode(reader: R, mode: RecordScanMode) -> Result<Self> {
+        let mut iter = RecordIter::from_reader_with_mode(reader, mode)?;
+        let records = iter.collect_records()?;
+        let RecordIter {
+            reader,
+            header,
+            records_start_pos,
+            scanner,
+            ..
+        } = iter;
         let archive = Self {
             source: ArchiveSource::new(reader),
             header,
             records_start_pos,
             records,
+            gaps: scanner.gaps,
+            offset_records: scanner.offset_records,
+            truncated: scanner.truncated,
+            name_errors: vec![],
         };
         Ok(archive)
     }
 
+    /// Creates an Archive from the records of a best-effort scan done before, so they aren't
+    /// read again. `reader` has to start at the file header.
+    pub(crate) fn from_scanned(
+        reader: R,
+        header: FileHeader,
+        records: Vec<Record>,
+        offset_records: Vec<OffsetRecord>,
+    ) -> Self {
+        Self {
+            source: ArchiveSource::new(reader),
+            header,
+            records_start_pos: std::mem::size_of::<FileHeader>() as u64,
+            records,
+            gaps: vec![],
+            offset_records,
+            truncated: None,
+            name_errors: vec![],
+        }
+    }
+
     /// Returns the archive records
     pub fn records(&self) -> &[Record] {
         &self.records
@@ -273,11 +845,100 @@ impl<R: Read + Seek> Archive<R> {
         self.records_start_pos
     }
 
+    /// Returns the unreadable data skipped in recovery mode. This is empty in other modes.
+    pub fn gaps(&self) -> &[RecoveryGap] {
+        &self.gaps
+    }
+
+    /// Returns a summary of the header and the records of the archive.
+    pub fn info(&self) -> ArchiveInfo {
+        ArchiveInfo::new(&self.header, &self.records)
+    }
+
+    /// Returns the offset records of the archive in order. The last one usually ends the archive.
+    pub fn offset_records(&self) -> &[OffsetRecord] {
+        &self.offset_records
+    }
+
+    /// Returns the position of the record cut off by the end of the data, if the archive is
+    /// truncated inside a record header, name or trailer.
+    pub fn truncated_at(&self) -> Option<u64> {
+        self.truncated
+    }
+
+    /// Convert the names of all records from `charset` to UTF-8. See [Record::transcode].
+    ///
+    /// Names which aren't valid in `charset` are kept and reported by [Archive::name_errors].
+    pub fn set_charset(&mut self, charset: Charset) {
+        self.name_errors = self
+            .records
+            .iter_mut()
+            .filter_map(|record| record.transcode(charset).err())
+            .collect();
+    }
+
+    /// Returns the names which couldn't be converted by [Archive::set_charset].
+    pub fn name_errors(&self) -> &[Error] {
+        &self.name_errors
+    }
+
     /// Finds a [Record] by its filename. Return [None] if the filename wasn't found.
     pub fn record_by_filename<P: AsRef<Path>>(&self, filename: P) -> Option<&Record> {
         record_by_filename(&self.records, filename)
     }
 
+    /// Checks the integrity of the archive beyond parsing it.
+    ///
+    /// Returns a [Diagnostic] for every problem found, ordered by offset. Compressed payloads are
+    /// decoded to check their size, so this reads the whole archive.
+    pub fn validate(&mut self) -> Result<Vec<Diagnostic>> {
+        let archive_len = self.source.len()?;
+        let mut diagnostics = validate::check_header(&self.header);
+        diagnostics.extend(validate::check_gaps(&self.gaps));
+        diagnostics.extend(validate::check_records(&self.records, archive_len));
+        diagnostics.extend(validate::check_end(
+            &self.records,
+            &self.offset_records,
+            self.truncated,
+            archive_len,
+        ));
+
+        for record in &self.records {
+            let compressed = record.magic() == HUFFMAN_MAGIC
+                && record.size() > 0
+                && record.file_position() + record.compressed_size() <= archive_len
+                && record
+                    .mode()
+                    .file_type()
+                    .is_some_and(|file_type| file_type.is_regular_file());
+            if !compressed {
+                continue;
+            }
+            let result = match self.source.open(record) {
+                Ok(Some(mut reader)) => io::copy(&mut reader, &mut io::sink())
+                    .map(|_| ())
+                    .map_err(Error::from),
+                Ok(None) => Ok(()),
+                Err(error) => Err(error),
+            };
+            let issue = match result {
+                Ok(()) => continue,
+                Err(Error::DecodedSizeMismatch { expected, actual }) => {
+                    Issue::DecodedSizeMismatch { expected, actual }
+                }
+                Err(error) => Issue::DecodingFailed(error.to_string()),
+            };
+            diagnostics.push(Diagnostic {
+                offset: record.file_position(),
+                path: Some(record.filename().to_path_buf()),
+                issue,
+            });
+        }
+
+        diagnostics.sort_by_key(|diagnostic| diagnostic.offset);
+        Ok(diagnostics)
+    }
+
     fn record_index_by_filename<P: AsRef<Path>>(&self, filename: P) -> Option<usize> {
         record_index_by_filename(&self.records, filename)
     }
@@ -342,14 +1003,14 @@ impl<R: Read + Seek> Archive<R> {
     }
 
     /// Extract the whole archive to a target directory and filter the files by a callback function.
-    pub fn extract<'a, P: AsRef<Path>>(&'a mut self, destination: P) -> Result<()> {
+    pub fn extract<P: AsRef<Path>>(&mut self, destination: P) -> Result<()> {
         self.extract_when(destination, |_| true)
     }
 
     /// Extract the whole archive to a target directory and filter the files by a callback function.
     ///
     /// `when` is a callback function returning `true` to extract the record or `false` to skip the record.
-    pub fn extract_when<'a, P, C>(&'a mut self, destination: P, when: C) -> Result<()>
+    pub fn extract_when<P, C>(&mut self, destination: P, when: C) -> Result<()>
     where
         P: AsRef<Path>,
         C: Fn(&Record) -> bool,
@@ -360,8 +1021,8 @@ impl<R: Read + Seek> Archive<R> {
     /// Extract the whole archive to a target directory and filter the files by a callback function and set file modes to be extracted.
     ///
     /// `when` is a callback function returning `true` to extract the record or `false` to skip the record.
-    pub fn extract_when_with_attr<'a, P, C>(
-        &'a mut self,
+    pub fn extract_when_with_attr<P, C>(
+        &mut self,
         destination: P,
         attributes: u8,
         when: C,
@@ -382,8 +1043,8 @@ impl<R: Read + Seek> Archive<R> {
     }
 
     /// Extract the whole archive in best-effort mode and return a report.
-    pub fn extract_when_best_effort_with_attr<'a, P, C>(
-        &'a mut self,
+    pub fn extract_when_best_effort_with_attr<P, C>(
+        &mut self,
         destination: P,
         attributes: u8,
         when: C,
@@ -396,47 +1057,14 @@ impl<R: Read + Seek> Archive<R> {
         let mut report = ExtractionReport::default();
 
         for record in self.records.iter() {
-            if !when(record) {
-                continue;
-            }
-
-            let target_path =
-                match validated_extraction_path(destination.as_ref(), record.filename()) {
-                    Ok(path) => path.normalize(),
-                    Err(error) => {
-                        report.skipped_entries.push(SkippedEntry {
-                            record: record.filename().to_path_buf(),
-                            destination: destination.as_ref().to_path_buf(),
-                            error,
-                        });
-                        continue;
-                    }
-                };
-            match extract_record_best_effort_with_attr(source, record, &target_path, attributes) {
-                ExtractionDisposition::Extracted => {
-                    report.extracted_entries.push(ExtractedEntry {
-                        record: record.filename().to_path_buf(),
-                        destination: target_path,
-                    });
-                }
-                ExtractionDisposition::ExtractedWithWarning(message) => {
-                    report.extracted_entries.push(ExtractedEntry {
-                        record: record.filename().to_path_buf(),
-                        destination: target_path.clone(),
-                    });
-                    report.warnings.push(ExtractionWarning {
-                        record: record.filename().to_path_buf(),
-                        destination: target_path,
-                        message,
-                    });
-                }
-                ExtractionDisposition::Skipped(error) => {
-                    report.skipped_entries.push(SkippedEntry {
-                        record: record.filename().to_path_buf(),
-                        destination: target_path,
-                        error,
-                    });
-                }
+            if when(record) {
+                extract_record_into_report(
+                    source,
+                    record,
+                    destination.as_ref(),
+                    attributes,
+                    &mut report,
+                );
             }
         }
 
@@ -444,6 +1072,86 @@ impl<R: Read + Seek> Archive<R> {
     }
 }
 
+/// Extract a single record below `destination` in best-effort mode and add the result to `report`.
+fn extract_record_into_report<R: Read + Seek>(
+    source: &mut ArchiveSource<R>,
+    record: &Record,
+    destination: &Path,
+    attributes: u8,
+    report: &mut ExtractionReport,
+) {
+    let target_path = match validated_extraction_path(destination, record.filename()) {
+        Ok(path) => path.normalize(),
+        Err(error) => {
+            report.skipped_entries.push(SkippedEntry {
+                record: record.filename().to_path_buf(),
+                destination: destination.to_path_buf(),
+                error,
+            });
+            return;
+        }
+    };
+    match extract_record_best_effort_with_attr(source, record, &target_path, attributes) {
+        ExtractionDisposition::Extracted => {
+            report.extracted_entries.push(ExtractedEntry {
+                record: record.filename().to_path_buf(),
+                destination: target_path,
+            });
+        }
+        ExtractionDisposition::ExtractedWithWarning(message) => {
+            report.extracted_entries.push(ExtractedEntry {
+                record: record.filename().to_path_buf(),
+                destination: target_path.clone(),
+            });
+            report.warnings.push(ExtractionWarning {
+                record: record.filename().to_path_buf(),
+                destination: target_path,
+                message,
+            });
+        }
+        ExtractionDisposition::Skipped(error) => {
+            report.skipped_entries.push(SkippedEntry {
+                record: record.filename().to_path_buf(),
+                destination: target_path,
+                error,
+            });
+        }
+    }
+}
+
+/// Kind of entry a [Record] describes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum EntryKind {
+    Directory,
+    File,
+    Symlink,
+    CharacterDevice,
+    BlockDevice,
+    Fifo,
+    Socket,
+    /// NFS4 ACL text of the record stored in front of it
+    AclText,
+    /// The mode holds no known file type.
+    Unknown,
+}
+
+impl Display for EntryKind {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let name = match self {
+            Self::Directory => "directory",
+            Self::File => "file",
+            Self::Symlink => "symlink",
+            Self::CharacterDevice => "character device",
+            Self::BlockDevice => "block device",
+            Self::Fifo => "fifo",
+            Self::Socket => "socket",
+            Self::AclText => "acl text",
+            Self::Unknown => "unknown",
+        };
+        write!(f, "{name}")
+    }
+}
+
 /// Container for all record data
 #[derive(Clone, Debug)]
 pub struct Record {
@@ -462,9 +1170,12 @@ pub struct Record {
 
 #[derive(Clone, Debug)]
 struct RecordRaw {
+    filename: Vec<u8>,
+    symlink: Option<Vec<u8>>,
     header: RecordHeader,
     record_acl: RecordAcl,
-    file_position: u32,
+    header_position: u64,
+    file_position: u64,
 }
 
 impl Record {
@@ -472,14 +1183,15 @@ impl Record {
         header: RecordHeader,
         record_acl: RecordAcl,
         acl_payload: Option<Vec<u8>>,
-        filename: PathBuf,
-        symlink: Option<PathBuf>,
-        file_position: u32,
+        filename: Vec<u8>,
+        symlink: Option<Vec<u8>>,
+        header_position: u64,
+        file_position: u64,
     ) -> Self {
         let acl = build_acl_data(header.mode, &record_acl, acl_payload);
         Self {
-            filename,
-            symlink,
+            filename: path_from_bytes(&filename),
+            symlink: symlink.as_deref().map(path_from_bytes),
             mode: Mode::from(header.mode),
             mdate: DateTime::from_timestamp(header.mtime as i64, 0)
                 .map(|dt| dt.naive_local())
@@ -489,8 +1201,11 @@ impl Record {
                 .unwrap_or_else(|| Utc::now().naive_local()),
             acl,
             raw: RecordRaw {
+                filename,
+                symlink,
                 header,
                 record_acl,
+                header_position,
                 file_position,
             },
         }
@@ -499,14 +1214,38 @@ impl Record {
     pub fn filename(&self) -> &Path {
         &self.filename
     }
+    /// Returns the filename as stored in the archive, without any conversion of its encoding.
+    pub fn filename_bytes(&self) -> &[u8] {
+        &self.raw.filename
+    }
     pub fn symlink(&self) -> Option<&Path> {
         self.symlink.as_ref().map(|pb| pb.as_ref())
     }
-    pub fn compressed_size(&self) -> u32 {
-        self.raw.header.compressed_size
+    /// Convert the filename and the symlink target from `charset` to UTF-8.
+    ///
+    /// The names are converted from the bytes stored in the archive. If a name isn't valid in
+    /// `charset`, the record is left unchanged and [Error::InvalidNameEncoding] is returned.
+    pub fn transcode(&mut self, charset: Charset) -> Result<()> {
+        let decode = |bytes: &[u8]| {
+            charset
+                .decode(bytes)
+                .map(PathBuf::from)
+                .ok_or_else(|| Error::InvalidNameEncoding {
+                    name: path_from_bytes(bytes),
+                    charset,
+                })
+        };
+        let filename = decode(&self.raw.filename)?;
+        let symlink = self.raw.symlink.as_deref().map(decode).transpose()?;
+        self.filename = filename;
+        self.symlink = symlink;
+        Ok(())
+    }
+    pub fn compressed_size(&self) -> u64 {
+        self.raw.header.payload_size()
     }
-    pub fn size(&self) -> u32 {
-        self.raw.header.size
+    pub fn size(&self) -> u64 {
+        self.raw.header.file_size()
     }
     pub fn mode(&self) -> &Mode {
         &self.mode
@@ -523,14 +1262,46 @@ impl Record {
     pub fn adate(&self) -> &NaiveDateTime {
         &self.adate
     }
-    pub fn file_position(&self) -> u32 {
+    /// Returns the position of the record header in the archive.
+    pub fn header_position(&self) -> u64 {
+        self.raw.header_position
+    }
+    pub fn file_position(&self) -> u64 {
         self.raw.file_position
     }
     pub fn magic(&self) -> u16 {
         self.raw.header.magic
     }
-    pub fn acl(&self) -> Option<&AclData> {
-        self.acl.as_ref()
+    /// Returns the kind of entry, decoded from the file type of the mode.
+    pub fn kind(&self) -> EntryKind {
+        if is_synthetic_acl_record(self) {
+            return EntryKind::AclText;
+        }
+        match self.mode.file_type() {
+            Some(FileType::Directory) => EntryKind::Directory,
+            Some(FileType::RegularFile) => EntryKind::File,
+            Some(FileType::SymbolicLink) => EntryKind::Symlink,
+            Some(FileType::CharacterDevice) => EntryKind::CharacterDevice,
+            Some(FileType::BlockDevice) => EntryKind::BlockDevice,
+            Some(FileType::FIFO) => EntryKind::Fifo,
+            Some(FileType::Socket) => EntryKind::Socket,
+            None => EntryKind::Unknown,
+        }
+    }
+    /// Returns the amount of hard links to the file on the backed up system.
+    pub fn link_count(&self) -> u16 {
+        self.raw.header.link_count()
+    }
+    /// Returns the inode number of the file on the backed up file system.
+    pub fn inode(&self) -> u32 {
+        self.raw.header.inode()
+    }
+    /// Returns the device holding the file on the backed up system.
+    pub fn device(&self) -> DeviceId {
+        self.raw.header.device()
+    }
+    pub fn acl(&self) -> Option<&AclData> {
+        self.acl.as_ref()
     }
 
     pub fn format_acl<F, G>(&self, resolve_uid: F, resolve_gid: G) -> Option<String>
@@ -565,50 +1336,66 @@ where
     record.format_acl(resolve_uid, resolve_gid)
 }
 
-fn attach_nfs4_acl_texts<R: Read + Seek>(reader: &mut R, records: &mut [Record]) -> Result<()> {
-    let mut source = ArchiveSource::new(reader);
-    let mut pending_nfs4 = Vec::new();
+/// Returns `true` if the record holds the ACL text of another record instead of a file.
+pub(crate) fn is_synthetic_acl_record(record: &Record) -> bool {
+    record
+        .mode()
+        .file_type()
+        .is_some_and(|file_type| file_type.is_regular_file())
+        && record.filename().to_string_lossy().ends_with('/')
+}
 
-    for index in 0..records.len() {
+/// Attaches the NFS4 ACL texts stored in separate records to the records they belong to. The
+/// separate records follow the records with NFS4 ACLs.
+#[derive(Default)]
+struct Nfs4AclTexts {
+    /// Indexes of the records with NFS4 ACLs still missing their text
+    pending: Vec<usize>,
+}
+
+impl Nfs4AclTexts {
+    /// Process the last record of `records`. `read_text` reads the payload of a separate ACL
+    /// record as text.
+    fn add<F>(&mut self, records: &mut [Record], read_text: F) -> Result<()>
+    where
+        F: FnOnce(&Record) -> Result<Option<String>>,
+    {
+        let Some(index) = records.len().checked_sub(1) else {
+            return Ok(());
+        };
         if records[index]
             .acl()
             .is_some_and(|acl| acl.acl_mode() & AIXC_ACL_MODE_FLAG == 0)
         {
-            pending_nfs4.push(index);
+            self.pending.push(index);
         }
 
-        let is_synthetic_acl_record = records[index]
-            .mode()
-            .file_type()
-            .is_some_and(|file_type| file_type.is_regular_file())
-            && records[index].filename().to_string_lossy().ends_with('/');
-
-        if !is_synthetic_acl_record {
-            continue;
+        if !is_synthetic_acl_record(&records[index]) {
+            return Ok(());
         }
 
-        let Some(text) = source.read_text(&records[index])? else {
-            continue;
+        let Some(text) = read_text(&records[index])? else {
+            return Ok(());
         };
 
         if !text.starts_with("*\n* ACL_type   NFS4") {
-            continue;
+            return Ok(());
         }
 
-        if let Some(target_index) = pending_nfs4.pop() {
-            if let Some(acl) = records[target_index].acl.as_mut() {
-                acl.attach_nfs4_text(text);
-            }
+        if let Some(target_index) = self.pending.pop()
+            && let Some(acl) = records[target_index].acl.as_mut()
+        {
+            acl.attach_nfs4_text(text);
         }
+        Ok(())
     }
-
-    Ok(())
 }
 
 #[cfg(test)]
 mod tests {
     use crate::bff;
     use crate::extract::{ArchiveSource, extract_file, set_file_attributes};
+    use crate::writer::archive_with_files;
 
     use super::*;
     use filetime::FileTime;
@@ -687,6 +1474,107 @@ mod tests {
         assert!(result.unwrap().is_none());
     }
 
+    #[test]
+    fn test_rejects_backup_by_inode() {
+        let mut bytes = vec![];
+        open_bff_file("test.bff")
+            .unwrap()
+            .read_to_end(&mut bytes)
+            .unwrap();
+        // Device and file system of the backup instead of `by name`
+        bytes[20..28].copy_from_slice(b"/dev/hd4");
+        bytes[36..44].copy_from_slice(b"/\0\0\0\0\0\0\0");
+        let checksum = compute_file_header_checksum(&bytes[..72]).unwrap();
+        bytes[4..6].copy_from_slice(&checksum.to_le_bytes());
+
+        assert!(matches!(
+            read_file_header(&mut bytes.as_slice()),
+            Err(Error::UnsupportedByInodeBackup)
+        ));
+        assert!(matches!(
+            Archive::new(io::Cursor::new(bytes)),
+            Err(Error::UnsupportedByInodeBackup)
+        ));
+    }
+
+    #[test]
+    fn test_read_offset_record() {
+        let archive = Archive::new(open_bff_file("test.bff").unwrap()).unwrap();
+
+        let offset_records = archive.offset_records();
+        assert_eq!(offset_records.len(), 1);
+        assert_eq!(offset_records[0].position, 584);
+        assert_eq!(offset_records[0].len, 8);
+        assert_eq!(offset_records[0].stored_checksum(), 0x0a84);
+        assert!(offset_records[0].is_checksum_valid());
+        assert_eq!(archive.records().len(), 4);
+    }
+
+    #[test]
+    fn test_recover_continues_behind_offset_record() {
+        let mut data =
+            archive_with_files(&[("./first.txt", b"first"), ("./second.txt", b"second")]);
+        let second = data
+            .windows(12)
+            .position(|window| window == b"./second.txt")
+            .unwrap()
+            - std::mem::size_of::<RecordHeader>();
+        let marker = data[data.len() - SHORT_HEADER_LEN..].to_vec();
+        data.splice(second..second, marker);
+
+        let archive = Archive::new(io::Cursor::new(&data)).unwrap();
+        assert_eq!(archive.records().len(), 1);
+        assert_eq!(archive.offset_records().len(), 1);
+
+        let mut archive = Archive::recover(io::Cursor::new(data)).unwrap();
+
+        let names = archive
+            .records()
+            .iter()
+            .map(|record| record.filename().to_str().unwrap())
+            .collect::<Vec<_>>();
+        assert_eq!(names, ["./first.txt", "./second.txt"]);
+        assert_eq!(archive.offset_records().len(), 2);
+        assert_eq!(archive.offset_records()[0].position, second as u64);
+        let mut content = String::new();
+        archive
+            .file("./second.txt")
+            .unwrap()
+            .unwrap()
+            .read_to_string(&mut content)
+            .unwrap();
+        assert_eq!(content, "second");
+    }
+
+    #[test]
+    fn test_record_kind_links_inode_and_device() {
+        let archive = Archive::new(open_bff_file("test.bff").unwrap()).unwrap();
+
+        let record = archive.record_by_filename("backup/file.txt").unwrap();
+        assert_eq!(record.kind(), EntryKind::File);
+        assert_eq!(record.link_count(), 1);
+        assert_eq!(
+            record.device(),
+            DeviceId {
+                major: 10,
+                minor: 8
+            }
+        );
+        let record = archive.record_by_filename("backup").unwrap();
+        assert_eq!(record.kind(), EntryKind::Directory);
+        assert_eq!(record.link_count(), 3);
+        assert_eq!(record.inode(), 0xFFFF_FFFF);
+        assert_eq!(record.kind().to_string(), "directory");
+
+        let archive = Archive::new(open_bff_file("acl_aixc_nfs4.bff").unwrap()).unwrap();
+        let kinds = archive
+            .records()
+            .iter()
+            .map(|record| record.kind())
+            .collect::<Vec<_>>();
+        assert_eq!(kinds.last(), Some(&EntryKind::AclText));
+    }
+
     #[test]
     fn test_read_records() {
         let mut file = open_bff_file("test.bff").unwrap();
@@ -700,6 +1588,351 @@ mod tests {
         assert_eq!(records.len(), 4);
     }
 
+    fn damaged_archive() -> (Vec<u8>, usize, usize) {
+        let mut archive = archive_with_files(&[
+            ("./first.txt", b"first"),
+            ("./second.txt", b"second"),
+            ("./third.txt", b"third"),
+        ]);
+        let record_start = |name: &[u8]| {
+            archive
+                .windows(name.len())
+                .position(|window| window == name)
+                .unwrap()
+                - std::mem::size_of::<RecordHeader>()
+        };
+        let second = record_start(b"./second.txt");
+        let third = record_start(b"./third.txt");
+        // Overwrite the second record with garbage
+        archive[second..second + 16].fill(0xFF);
+        (archive, second, third)
+    }
+
+    #[test]
+    fn test_recover_resynchronizes_after_damaged_record() {
+        let (data, second, third) = damaged_archive();
+
+        let mut archive = Archive::recover(io::Cursor::new(data)).unwrap();
+
+        let names = archive
+            .records()
+            .iter()
+            .map(|record| record.filename().to_str().unwrap())
+            .collect::<Vec<_>>();
+        assert_eq!(names, ["./first.txt", "./third.txt"]);
+        assert_eq!(
+            archive.gaps(),
+            [RecoveryGap {
+                start: second as u64,
+                end: third as u64
+            }]
+        );
+        let mut content = String::new();
+        archive
+            .file("./third.txt")
+            .unwrap()
+            .unwrap()
+            .read_to_string(&mut content)
+            .unwrap();
+        assert_eq!(content, "third");
+    }
+
+    #[test]
+    fn test_recover_resynchronizes_after_corrupt_payload_size() {
+        let mut data = archive_with_files(&[
+            ("./first.txt", b"first"),
+            ("./second.txt", b"second"),
+            ("./third.txt", b"third"),
+        ]);
+        let record_start = |name: &[u8]| {
+            data.windows(name.len())
+                .position(|window| window == name)
+                .unwrap()
+                - std::mem::size_of::<RecordHeader>()
+        };
+        let second = record_start(b"./second.txt");
+        let third = record_start(b"./third.txt");
+        // Let the payload size of the second record point behind the end.
+        data[second + 56..second + 60].copy_from_slice(&0x100000u32.to_le_bytes());
+
+        let archive = Archive::scan(io::Cursor::new(data.clone())).unwrap();
+        assert_eq!(archive.records().len(), 2);
+
+        let archive = Archive::recover(io::Cursor::new(data)).unwrap();
+        let names = archive
+            .records()
+            .iter()
+            .map(|record| record.filename().to_str().unwrap())
+            .collect::<Vec<_>>();
+        assert_eq!(names, ["./first.txt", "./second.txt", "./third.txt"]);
+        assert_eq!(
+            archive.gaps(),
+            [RecoveryGap {
+                start: archive.records()[1].file_position(),
+                end: third as u64
+            }]
+        );
+    }
+
+    #[test]
+    fn test_recover_reports_gap_up_to_end() {
+        let (mut data, second, _) = damaged_archive();
+        data.truncate(second + 100);
+
+        let archive = Archive::recover(io::Cursor::new(data)).unwrap();
+
+        assert_eq!(archive.records().len(), 1);
+        assert_eq!(
+            archive.gaps(),
+            [RecoveryGap {
+                start: second as u64,
+                end: second as u64 + 100
+            }]
+        );
+        assert_eq!(
+            archive.gaps()[0].to_string(),
+            format!(
+                "Skipped 100 bytes of unreadable data from offset {second} to {}.",
+                second + 100
+            )
+        );
+    }
+
+    #[test]
+    fn test_scan_has_no_gaps() {
+        let archive = Archive::scan(open_bff_file("test.bff").unwrap()).unwrap();
+
+        assert!(archive.gaps().is_empty());
+    }
+
+    #[test]
+    fn test_record_iter_yields_records_lazily() {
+        let file = open_bff_file("test.bff").unwrap();
+
+        let mut iter = RecordIter::new(file).unwrap();
+
+        assert_eq!(iter.records_start_pos(), 72);
+        let first = iter.next().unwrap().unwrap();
+        assert_eq!(first.filename(), Path::new("backup"));
+        assert_eq!(iter.count(), 3);
+    }
+
+    #[test]
+    fn test_record_iter_reads_payload_in_place() {
+        let mut writer = crate::writer::ArchiveWriter::new(Vec::new()).unwrap();
+        writer
+            .append_file("./a.txt", &Default::default(), b"first")
+            .unwrap();
+        writer
+            .append_file_compressed("./b.txt", &Default::default(), &b"second".repeat(20))
+            .unwrap();
+        writer
+            .append_file("./c.txt", &Default::default(), b"third")
+            .unwrap();
+        let bytes = writer.finish().unwrap();
+
+        let mut iter = RecordIter::new(std::io::Cursor::new(bytes)).unwrap();
+        let mut contents = vec![];
+        while let Some(record) = iter.next() {
+            let record = record.unwrap();
+            if record.filename() == Path::new("./a.txt") {
+                // Skip the payload
+                continue;
+            }
+            // Read partially only, the next record has to be found anyway.
+            let mut content = [0u8; 3];
+            let mut reader = iter.payload().unwrap().unwrap();
+            reader.read_exact(&mut content).unwrap();
+            contents.push(content);
+        }
+
+        assert_eq!(contents, vec![*b"sec", *b"thi"]);
+        assert!(iter.payload().unwrap().is_none());
+    }
+
+    #[test]
+    fn test_record_iter_lists_forward_only_stream() {
+        let file = open_bff_file("acl_aixc_nfs4.bff").unwrap();
+        let expected: Vec<_> = Archive::scan(open_bff_file("acl_aixc_nfs4.bff").unwrap())
+            .unwrap()
+            .records()
+            .iter()
+            .map(|record| record.filename().to_path_buf())
+            .collect();
+
+        let names: Vec<_> = RecordIter::scan(crate::stream::ForwardReader::new(file))
+            .unwrap()
+            .map(|record| record.unwrap().filename().to_path_buf())
+            .collect();
+
+        assert_eq!(names, expected);
+    }
+
+    #[test]
+    fn test_record_iter_collects_nfs4_acl_texts_of_forward_only_stream() {
+        let file = open_bff_file("acl_aixc_nfs4.bff").unwrap();
+        let archive = Archive::scan(open_bff_file("acl_aixc_nfs4.bff").unwrap()).unwrap();
+
+        let records = RecordIter::scan(crate::stream::ForwardReader::new(file))
+            .unwrap()
+            .collect_records()
+            .unwrap();
+
+        let text = |record: &Record| {
+            record
+                .acl()
+                .and_then(|acl| acl.as_nfs4())
+                .and_then(|nfs4| nfs4.text.clone())
+        };
+        assert!(text(&records[3]).is_some());
+        assert_eq!(
+            records.iter().map(text).collect::<Vec<_>>(),
+            archive.records().iter().map(text).collect::<Vec<_>>()
+        );
+    }
+
+    #[test]
+    fn test_record_iter_extracts_forward_only_stream() {
+        let data = b"streamed and compressed".repeat(50);
+        let mut writer = crate::writer::ArchiveWriter::new(Vec::new()).unwrap();
+        writer
+            .append_directory("./dir", &Default::default())
+            .unwrap();
+        writer
+            .append_file_compressed("./dir/packed.txt", &Default::default(), &data)
+            .unwrap();
+        writer
+            .append_file("./dir/raw.txt", &Default::default(), b"raw")
+            .unwrap();
+        let bytes = writer.finish().unwrap();
+        let temp_dir = tempdir().unwrap();
+
+        let mut iter =
+            RecordIter::new(crate::stream::ForwardReader::new(bytes.as_slice())).unwrap();
+        let report = iter
+            .extract_when_best_effort_with_attr(temp_dir.path(), attribute::ATTRIBUTE_NONE, |_| {
+                true
+            })
+            .unwrap();
+
+        assert_eq!(report.extracted_entries.len(), 3);
+        assert!(report.skipped_entries.is_empty());
+        assert_eq!(
+            fs::read(temp_dir.path().join("dir/packed.txt")).unwrap(),
+            data
+        );
+        assert_eq!(
+            fs::read(temp_dir.path().join("dir/raw.txt")).unwrap(),
+            b"raw"
+        );
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_extract_keeps_legacy_code_page_names() {
+        use std::{ffi::OsStr, os::unix::ffi::OsStrExt};
+
+        // "café.txt" and "cafè.txt" in ISO8859-1, both invalid UTF-8
+        let acute = OsStr::from_bytes(b"./caf\xE9.txt");
+        let grave = OsStr::from_bytes(b"./caf\xE8.txt");
+        let mut writer = crate::writer::ArchiveWriter::new(Vec::new()).unwrap();
+        writer
+            .append_file(acute, &Default::default(), b"acute")
+            .unwrap();
+        writer
+            .append_file(grave, &Default::default(), b"grave")
+            .unwrap();
+        let mut archive = Archive::new(io::Cursor::new(writer.finish().unwrap())).unwrap();
+        let temp_dir = tempdir().unwrap();
+
+        archive.extract(temp_dir.path()).unwrap();
+
+        let records = archive.records();
+        assert_eq!(records[0].filename_bytes(), b"./caf\xE9.txt");
+        assert_eq!(records[1].filename_bytes(), b"./caf\xE8.txt");
+        assert_ne!(records[0].filename(), records[1].filename());
+        assert_eq!(
+            fs::read(temp_dir.path().join(OsStr::from_bytes(b"caf\xE9.txt"))).unwrap(),
+            b"acute"
+        );
+        assert_eq!(
+            fs::read(temp_dir.path().join(OsStr::from_bytes(b"caf\xE8.txt"))).unwrap(),
+            b"grave"
+        );
+    }
+
+    #[cfg(unix)]
+    fn japanese_archive() -> Vec<u8> {
+        use std::{ffi::OsStr, os::unix::ffi::OsStrExt};
+
+        // "日本.txt" in IBM-943 and a link to it, followed by a name which isn't IBM-943
+        let mut writer = crate::writer::ArchiveWriter::new(Vec::new()).unwrap();
+        writer
+            .append_file(
+                OsStr::from_bytes(b"./\x93\xfa\x96\x7b.txt"),
+                &Default::default(),
+                b"nihon",
+            )
+            .unwrap();
+        writer
+            .append_symlink(
+                "./link",
+                OsStr::from_bytes(b"\x93\xfa\x96\x7b.txt"),
+                &Default::default(),
+            )
+            .unwrap();
+        writer
+            .append_file(OsStr::from_bytes(b"./broken\x93"), &Default::default(), b"")
+            .unwrap();
+        writer.finish().unwrap()
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_set_charset_converts_names() {
+        let mut archive = Archive::new(io::Cursor::new(japanese_archive())).unwrap();
+        let temp_dir = tempdir().unwrap();
+
+        archive.set_charset(Charset::Ibm943);
+        archive.extract(temp_dir.path()).unwrap();
+
+        let records = archive.records();
+        assert_eq!(records[0].filename(), Path::new("./日本.txt"));
+        assert_eq!(records[0].filename_bytes(), b"./\x93\xfa\x96\x7b.txt");
+        assert_eq!(records[1].symlink(), Some(Path::new("日本.txt")));
+        assert_eq!(
+            fs::read(temp_dir.path().join("日本.txt")).unwrap(),
+            b"nihon"
+        );
+        assert_eq!(fs::read(temp_dir.path().join("link")).unwrap(), b"nihon");
+        // The name which can't be converted is reported and kept as stored.
+        assert_eq!(archive.name_errors().len(), 1);
+        assert!(matches!(
+            &archive.name_errors()[0],
+            Error::InvalidNameEncoding {
+                charset: Charset::Ibm943,
+                ..
+            }
+        ));
+        assert_eq!(records[2].filename_bytes(), b"./broken\x93");
+        assert!(temp_dir.path().join(records[2].filename()).exists());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_record_iter_converts_names() {
+        let mut iter = RecordIter::new(io::Cursor::new(japanese_archive())).unwrap();
+        iter.set_charset(Charset::Ibm943);
+
+        let names = iter
+            .by_ref()
+            .map(|record| record.unwrap().filename().to_path_buf())
+            .collect::<Vec<_>>();
+
+        assert_eq!(names[0], Path::new("./日本.txt"));
+        assert_eq!(iter.name_errors().len(), 1);
+    }
+
     #[test]
     fn test_record_by_filename() {
         let mut file = open_bff_file("test.bff").unwrap();
@@ -761,9 +1994,10 @@ mod tests {
             record_header,
             Default::default(),
             None,
-            PathBuf::from("mock_file.txt"),
+            b"mock_file.txt".to_vec(),
             None,
             0,
+            0,
         );
         let temp_dir = tempdir().unwrap();
         let file_path = temp_dir.path().join("mock_file.txt");
@@ -796,9 +2030,10 @@ mod tests {
             record_header,
             Default::default(),
             None,
-            PathBuf::from("mock_file.txt"),
+            b"mock_file.txt".to_vec(),
             None,
             0,
+            0,
         );
         let temp_dir = tempdir().unwrap();
         let file_path = temp_dir.path().join("mock_file.txt");
@@ -880,8 +2115,9 @@ mod tests {
             },
             Default::default(),
             None,
-            PathBuf::from("backup/link.txt"),
-            Some(PathBuf::from("target.txt")),
+            b"backup/link.txt".to_vec(),
+            Some(b"target.txt".to_vec()),
+            0,
             0,
         );
 
@@ -901,7 +2137,7 @@ mod tests {
         let result = validated_extraction_path(temp_dir.path(), Path::new("../escape.txt"));
 
         assert!(
-            matches!(result, Err(Error::InvalidExtractionPath(path)) if path == PathBuf::from("../escape.txt"))
+            matches!(result, Err(Error::InvalidExtractionPath(path)) if path == Path::new("../escape.txt"))
         );
     }
 
@@ -931,7 +2167,7 @@ mod tests {
         );
 
         assert!(
-            matches!(result, Err(Error::InvalidExtractionPath(path)) if path == PathBuf::from("../escape.txt"))
+            matches!(result, Err(Error::InvalidExtractionPath(path)) if path == Path::new("../escape.txt"))
         );
         assert!(!outside_path.exists());
     }
@@ -955,10 +2191,322 @@ mod tests {
         assert!(report
             .skipped_entries
             .iter()
-            .any(|entry| entry.record == PathBuf::from("../escape.txt")
+            .any(|entry| entry.record == Path::new("../escape.txt")
                 && matches!(entry.error, Error::InvalidExtractionPath(ref path) if path == &PathBuf::from("../escape.txt"))));
     }
 
+    fn compressed_archive_with_size(data: &[u8], size: u32) -> Vec<u8> {
+        let mut writer = crate::writer::ArchiveWriter::new(Vec::new()).unwrap();
+        writer
+            .append_file_compressed("./file.txt", &Default::default(), data)
+            .unwrap();
+        let mut bytes = writer.finish().unwrap();
+        // Record header follows the 72 byte file header, `size` is at offset 24.
+        bytes[96..100].copy_from_slice(&size.to_le_bytes());
+        bytes
+    }
+
+    #[test]
+    fn test_best_effort_extract_reports_huffman_size_mismatch() {
+        let data = b"compressed content ".repeat(10);
+        let bytes = compressed_archive_with_size(&data, data.len() as u32 + 5);
+        let temp_dir = tempdir().unwrap();
+
+        let mut archive = Archive::new(std::io::Cursor::new(bytes)).unwrap();
+        let report = archive
+            .extract_when_best_effort_with_attr(temp_dir.path(), attribute::ATTRIBUTE_NONE, |_| {
+                true
+            })
+            .unwrap();
+
+        assert!(report.extracted_entries.is_empty());
+        assert_eq!(report.skipped_entries.len(), 1);
+        let expected = data.len() as u64 + 5;
+        let actual = data.len() as u64;
+        assert!(matches!(
+            report.skipped_entries[0].error,
+            Error::DecodedSizeMismatch { expected: e, actual: a } if e == expected && a == actual
+        ));
+        assert!(!temp_dir.path().join("file.txt").exists());
+    }
+
+    #[test]
+    fn test_validate_reports_huffman_size_mismatch() {
+        let data = b"compressed content ".repeat(10);
+        let bytes = compressed_archive_with_size(&data, data.len() as u32 + 5);
+
+        let mut archive = Archive::new(std::io::Cursor::new(bytes)).unwrap();
+        let diagnostics = archive.validate().unwrap();
+
+        assert_eq!(diagnostics.len(), 1);
+        assert!(diagnostics[0].is_error());
+        assert_eq!(diagnostics[0].offset, archive.records()[0].file_position());
+        assert_eq!(
+            diagnostics[0].issue,
+            Issue::DecodedSizeMismatch {
+                expected: data.len() as u64 + 5,
+                actual: data.len() as u64
+            }
+        );
+    }
+
+    #[test]
+    fn test_validate_accepts_sample_archives() {
+        for filename in ["test.bff", "acl_aixc_nfs4.bff"] {
+            let mut archive = Archive::scan(open_bff_file(filename).unwrap()).unwrap();
+            let diagnostics = archive.validate().unwrap();
+
+            assert!(
+                diagnostics.iter().all(|diagnostic| !diagnostic.is_error()),
+                "{filename}: {diagnostics:?}"
+            );
+        }
+    }
+
+    #[test]
+    fn test_validate_reports_unreadable_data() {
+        let (data, second, third) = damaged_archive();
+
+        let mut archive = Archive::recover(io::Cursor::new(data)).unwrap();
+        let diagnostics = archive.validate().unwrap();
+
+        assert_eq!(diagnostics.len(), 1);
+        assert_eq!(diagnostics[0].offset, second as u64);
+        assert_eq!(
+            diagnostics[0].issue,
+            Issue::UnreadableData {
+                len: (third - second) as u64
+            }
+        );
+    }
+
+    #[test]
+    fn test_validate_reports_payload_beyond_end() {
+        let (mut data, second, _) = damaged_archive();
+        data.truncate(second + 100);
+        // Let the first payload extend beyond the end of the archive
+        data[96..100].copy_from_slice(&1000u32.to_le_bytes());
+        data[128..132].copy_from_slice(&1000u32.to_le_bytes());
+
+        let mut archive = Archive::recover(io::Cursor::new(data)).unwrap();
+        let issues = archive
+            .validate()
+            .unwrap()
+            .into_iter()
+            .map(|diagnostic| diagnostic.issue)
+            .collect::<Vec<_>>();
+
+        assert!(issues.contains(&Issue::PayloadBeyondEnd {
+            end: archive.records()[0].file_position() + 1000,
+            archive_len: second as u64 + 100
+        }));
+    }
+
+    #[test]
+    fn test_extract_rejects_huffman_overrun() {
+        let data = b"compressed content ".repeat(10);
+        let bytes = compressed_archive_with_size(&data, 7);
+        let temp_dir = tempdir().unwrap();
+
+        let mut archive = Archive::new(std::io::Cursor::new(bytes)).unwrap();
+        let result = archive.extract_file_by_name_with_attr(
+            "./file.txt",
+            temp_dir.path().join("file.txt"),
+            attribute::ATTRIBUTE_NONE,
+        );
+
+        assert!(matches!(
+            result,
+            Err(Error::DecodedSizeMismatch { expected: 7, .. })
+        ));
+    }
+
+    #[test]
+    fn test_record_reader_seeks_raw_record() {
+        let bytes = archive_with_files(&[("./raw.txt", b"0123456789"), ("./next.txt", b"next")]);
+
+        let mut archive = Archive::new(std::io::Cursor::new(bytes)).unwrap();
+        let mut reader = archive.file("./raw.txt").unwrap().unwrap();
+        let mut content = String::new();
+
+        reader.seek(SeekFrom::Start(6)).unwrap();
+        reader.read_to_string(&mut content).unwrap();
+        assert_eq!(content, "6789");
+
+        content.clear();
+        assert_eq!(reader.seek(SeekFrom::End(-7)).unwrap(), 3);
+        reader.read_to_string(&mut content).unwrap();
+        assert_eq!(content, "3456789");
+
+        let error = reader.seek(SeekFrom::Start(u64::MAX)).unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::InvalidInput);
+    }
+
+    /// Data consisting of `head`, `hole_len` zero bytes and `tail` without allocating the zeros.
+    struct SparseReader {
+        head: Vec<u8>,
+        hole_len: u64,
+        tail: Vec<u8>,
+        position: u64,
+    }
+
+    impl Read for SparseReader {
+        fn read(&mut self, buf: &mut [u8]) -> Result<usize> {
+            let head_len = self.head.len() as u64;
+            let tail_start = head_len + self.hole_len;
+            let len = if self.position < head_len {
+                let mut head = &self.head[self.position as usize..];
+                head.read(buf)?
+            } else if self.position < tail_start {
+                let len = buf.len().min((tail_start - self.position) as usize);
+                buf[..len].fill(0);
+                len
+            } else {
+                let start = (self.position - tail_start) as usize;
+                let mut tail = self.tail.get(start..).unwrap_or_default();
+                tail.read(buf)?
+            };
+            self.position += len as u64;
+            Ok(len)
+        }
+    }
+
+    impl Seek for SparseReader {
+        fn seek(&mut self, pos: SeekFrom) -> Result<u64> {
+            let len = self.head.len() as u64 + self.hole_len + self.tail.len() as u64;
+            self.position = match pos {
+                SeekFrom::Start(offset) => Some(offset),
+                SeekFrom::Current(offset) => self.position.checked_add_signed(offset),
+                SeekFrom::End(offset) => len.checked_add_signed(offset),
+            }
+            .ok_or_else(|| io::Error::from(io::ErrorKind::InvalidInput))?;
+            Ok(self.position)
+        }
+    }
+
+    #[test]
+    fn test_scan_large_file_beyond_4_gib() {
+        const BIG_SIZE: u64 = 0x1_0000_0008;
+        let mut bytes = archive_with_files(&[("./big", b""), ("./next.txt", b"next")]);
+        // Upper 32 bits of `size` and `compressed_size` of the first record
+        bytes[120..124].copy_from_slice(&1u32.to_le_bytes());
+        bytes[132..136].copy_from_slice(&1u32.to_le_bytes());
+        bytes[96..100].copy_from_slice(&8u32.to_le_bytes());
+        bytes[128..132].copy_from_slice(&8u32.to_le_bytes());
+
+        // The payload of the large file is a hole of zeros.
+        let payload_start = 184;
+        let reader = SparseReader {
+            head: bytes[..payload_start].to_vec(),
+            hole_len: BIG_SIZE,
+            tail: bytes[payload_start..].to_vec(),
+            position: 0,
+        };
+
+        let mut archive = Archive::new(reader).unwrap();
+        let records = archive.records();
+        assert_eq!(records[0].size(), BIG_SIZE);
+        assert_eq!(records[0].compressed_size(), BIG_SIZE);
+        assert!(records[1].file_position() > u64::from(u32::MAX));
+
+        let mut reader = archive.file("./big").unwrap().unwrap();
+        assert_eq!(reader.seek(SeekFrom::End(-4)).unwrap(), BIG_SIZE - 4);
+        let mut tail = vec![];
+        reader.read_to_end(&mut tail).unwrap();
+        assert_eq!(tail, [0; 4]);
+
+        let mut content = String::new();
+        archive
+            .file("./next.txt")
+            .unwrap()
+            .unwrap()
+            .read_to_string(&mut content)
+            .unwrap();
+        assert_eq!(content, "next");
+    }
+
+    #[test]
+    fn test_record_reader_seeks_huffman_record() {
+        let data: Vec<u8> = (0..400_000u32)
+            .map(|i| b"XCOFF header and tail"[(i as usize * 7 + i as usize / 13) % 21])
+            .collect();
+        let mut writer = crate::writer::ArchiveWriter::new(Vec::new()).unwrap();
+        writer
+            .append_file_compressed("./unix", &Default::default(), &data)
+            .unwrap();
+        let bytes = writer.finish().unwrap();
+
+        let mut archive = Archive::new(std::io::Cursor::new(bytes)).unwrap();
+        let mut reader = archive.file("./unix").unwrap().unwrap();
+        let mut buf = [0u8; 16];
+
+        reader.seek(SeekFrom::End(-16)).unwrap();
+        reader.read_exact(&mut buf).unwrap();
+        assert_eq!(buf, data[data.len() - 16..]);
+
+        reader.seek(SeekFrom::Start(270_000)).unwrap();
+        reader.read_exact(&mut buf).unwrap();
+        assert_eq!(buf, data[270_000..270_016]);
+
+        reader.seek(SeekFrom::Start(0)).unwrap();
+        reader.read_exact(&mut buf).unwrap();
+        assert_eq!(buf, data[..16]);
+    }
+
+    fn single_record_archive() -> Vec<u8> {
+        archive_with_files(&[("a", b"")])
+    }
+
+    #[test]
+    fn test_scan_rejects_oversized_acl_length() {
+        let mut bytes = single_record_archive();
+        // Set S_IXACL in `mode` and a huge `acl_len` in the trailer following the 8 byte name.
+        let mode = u32::from_le_bytes(bytes[84..88].try_into().unwrap()) | S_IXACL;
+        bytes[84..88].copy_from_slice(&mode.to_le_bytes());
+        bytes[152..156].copy_from_slice(&0xFFFF_FFF0u32.to_le_bytes());
+
+        let mut reader = std::io::Cursor::new(bytes);
+        reader.seek(SeekFrom::Start(72)).unwrap();
+
+        assert!(matches!(
+            read_next_record(&mut reader),
+            Err(Error::IoError(e)) if e.kind() == io::ErrorKind::UnexpectedEof
+        ));
+    }
+
+    #[test]
+    fn test_scan_handles_maximum_compressed_size() {
+        let mut bytes = single_record_archive();
+        bytes[96..100].copy_from_slice(&1u32.to_le_bytes());
+        bytes[128..132].copy_from_slice(&u32::MAX.to_le_bytes());
+
+        let archive = Archive::scan(std::io::Cursor::new(bytes)).unwrap();
+
+        assert_eq!(archive.records().len(), 1);
+        assert_eq!(archive.records()[0].compressed_size(), u64::from(u32::MAX));
+    }
+
+    #[test]
+    fn test_overflowing_payload_size_is_invalid_record() {
+        let mut bytes = include_bytes!("../../resources/test/test.bff").to_vec();
+        // Payload size of the first record including its upper 32 bits
+        bytes[72 + 56..72 + 64].fill(0xFF);
+        let open = |bytes: &[u8]| std::io::Cursor::new(bytes.to_vec());
+
+        assert!(matches!(
+            Archive::new(open(&bytes)),
+            Err(Error::InvalidRecord)
+        ));
+        let scanned = Archive::scan(open(&bytes)).unwrap();
+        let recovered = Archive::recover(open(&bytes)).unwrap();
+        assert!(!scanned.records().is_empty());
+        assert!(!recovered.records().is_empty());
+        assert!(matches!(
+            read_next_record(&mut open(&bytes[72..])),
+            Err(Error::InvalidRecord)
+        ));
+    }
+
     // -----------------------------------------------------------------------
     // ACL tests — use resources/test/test_acl.bff which has:
     //   record[0]: directory './'  with ACL (num_entries=5, acl_len=32)
@@ -1093,8 +2641,8 @@ mod tests {
         let archive = Archive::scan(file).unwrap();
         let records = archive.records();
 
-        assert_eq!(records[1].acl().unwrap().as_aixc().is_some(), true);
-        assert_eq!(records[3].acl().unwrap().as_nfs4().is_some(), true);
+        assert!(records[1].acl().unwrap().as_aixc().is_some());
+        assert!(records[3].acl().unwrap().as_nfs4().is_some());
     }
 
     #[test]
@@ -1116,7 +2664,7 @@ mod tests {
         let archive = Archive::new(file).unwrap();
         let acl = archive.records()[0].acl().unwrap();
 
-        assert_eq!(acl.as_aixc().is_some(), true);
+        assert!(acl.as_aixc().is_some());
     }
 
     #[test]
@@ -1,5 +1,6 @@
 use crate::Result;
-use std::io::Read;
+use chrono::{DateTime, Utc};
+use std::{fmt::Display, io::Read};
 
 /// All BFF files should contain this magic number.
 pub const FILE_MAGIC: u32 = 0xea6b0009; //0x09006BEA;
@@ -7,6 +8,12 @@ pub const FILE_MAGIC: u32 = 0xea6b0009; //0x09006BEA;
 pub const HUFFMAN_MAGIC: u16 = 0xEA6C;
 /// All records should contain one of these magic numbers.
 pub const HEADER_MAGICS: [u16; 3] = [0xEA6B, HUFFMAN_MAGIC, 0xEA6D];
+/// Format marker of records describing a file.
+pub const RECORD_FORMAT_MARKER: u8 = 0x0B;
+/// Format marker of an [OffsetRecord].
+pub const OFFSET_FORMAT_MARKER: u8 = 0x07;
+/// Length of the common header of all records: length, format marker, magic number and checksum.
+pub const SHORT_HEADER_LEN: usize = 8;
 
 /// Representation of the file header.
 ///
@@ -16,7 +23,8 @@ pub const HEADER_MAGICS: [u16; 3] = [0xEA6B, HUFFMAN_MAGIC, 0xEA6D];
 pub struct FileHeader {
     /// Magic number
     pub magic: u32,
-    /// Archive header checksum written by the AIX backup producer.
+    /// Archive header checksum written by the AIX backup producer in the lower 16 bits and the
+    /// volume number in the upper 16 bits, see [FileHeader::volume_number].
     pub checksum: u32,
     /// Archive creation time.
     pub current_date: u32,
@@ -52,6 +60,49 @@ impl FileHeader {
         (self.checksum & 0xFFFF) as u16
     }
 
+    /// Returns the number of the volume starting with this header, counting from 1.
+    ///
+    /// It is 1 in sampled single-volume archives. Its position matches the volume number
+    /// following the checksum in the volume header of the AIX dump format.
+    pub fn volume_number(&self) -> u16 {
+        (self.checksum >> 16) as u16
+    }
+
+    /// Returns the creation time of the archive.
+    pub fn current_date(&self) -> DateTime<Utc> {
+        DateTime::from_timestamp(i64::from(self.current_date), 0).unwrap_or_default()
+    }
+
+    /// Returns the start time of the backup run.
+    pub fn starting_date(&self) -> DateTime<Utc> {
+        DateTime::from_timestamp(i64::from(self.starting_date), 0).unwrap_or_default()
+    }
+
+    /// Returns the file system name, `by name` for backups by name.
+    pub fn disk_name(&self) -> String {
+        fixed_string(&self.disk_name)
+    }
+
+    /// Returns the device name, `by name` for backups by name.
+    pub fn filesystem_name(&self) -> String {
+        fixed_string(&self.filesystem_name)
+    }
+
+    /// Returns `true` if the header belongs to a backup by inode.
+    ///
+    /// Backups by inode store the device and the file system of the backup instead of `by name`.
+    /// Headers without names are taken as backups by name.
+    pub fn is_by_inode(&self) -> bool {
+        [self.disk_name(), self.filesystem_name()]
+            .iter()
+            .all(|name| !name.is_empty() && name != "by name")
+    }
+
+    /// Returns the name of the user who created the archive.
+    pub fn username(&self) -> String {
+        fixed_string(&self.username)
+    }
+
     /// Returns the constant sentinel stored at offset `0x10`.
     ///
     /// The exact AIX field name is still unknown, so this accessor keeps a
@@ -66,6 +117,65 @@ impl FileHeader {
     }
 }
 
+/// Representation of a record with the format marker [OFFSET_FORMAT_MARKER].
+///
+/// AIX writes it behind the last file record. In sampled archives it consists of the common
+/// record header only, which matches the `FS_END` record of the AIX dump format. Data following it
+/// up to the end of the tape block is padding, usually stale data of earlier records.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct OffsetRecord {
+    /// Position of the record in the archive
+    pub position: u64,
+    /// Length of the record in bytes. The header stores it in 8-byte units.
+    pub len: u64,
+    /// Magic number
+    pub magic: u16,
+    /// Checksum of the record. Only the lower 16 bits are validated.
+    pub checksum: u32,
+    /// Computed checksum of the record, `None` if the length is invalid.
+    pub computed_checksum: Option<u16>,
+    /// Data behind the common header. Empty in sampled archives.
+    pub data: Vec<u8>,
+}
+
+impl OffsetRecord {
+    /// Decode an offset record at `position` from its raw bytes including the common header.
+    pub(crate) fn parse(position: u64, bytes: &[u8]) -> Self {
+        Self {
+            position,
+            len: bytes.len() as u64,
+            magic: u16::from_le_bytes([bytes[2], bytes[3]]),
+            checksum: u32::from_le_bytes([bytes[4], bytes[5], bytes[6], bytes[7]]),
+            computed_checksum: compute_file_header_checksum(bytes),
+            data: bytes[SHORT_HEADER_LEN..].to_vec(),
+        }
+    }
+
+    /// Returns the checksum bits validated by AIX.
+    pub fn stored_checksum(&self) -> u16 {
+        self.checksum as u16
+    }
+
+    /// Returns `true` if the stored checksum matches the data of the record.
+    pub fn is_checksum_valid(&self) -> bool {
+        self.computed_checksum == Some(self.stored_checksum())
+    }
+
+    /// Returns the position behind the record.
+    pub fn end(&self) -> u64 {
+        self.position + self.len
+    }
+}
+
+/// Decode a fixed size, NUL padded string field and trim surrounding whitespace.
+fn fixed_string(bytes: &[u8]) -> String {
+    let len = bytes
+        .iter()
+        .position(|&byte| byte == 0)
+        .unwrap_or(bytes.len());
+    String::from_utf8_lossy(&bytes[..len]).trim().to_string()
+}
+
 /// Compute the AIX archive-header checksum for a raw file-header block.
 ///
 /// The block length is encoded in the first byte as a count of 8-byte units.
@@ -96,21 +206,22 @@ pub(crate) fn compute_file_header_checksum(block: &[u8]) -> Option<u16> {
 #[repr(C, packed)]
 #[derive(Debug, Copy, Clone)]
 pub struct RecordHeader {
-    /// Entry subtype byte emitted by the AIX backup writer.
+    /// Length of the header and the name in 8-byte units, see [RecordHeader::header_len].
     ///
-    /// This varies across directories, regular files, symlinks, and package metadata records,
-    /// but is not required for extraction because `mode` carries the actual Unix file type.
+    /// It differs between directories, files and symlinks only by the length of their names. The
+    /// kind of entry is carried by `mode`.
     pub unk00: u8,
-    /// Record format marker. Normal entries use `0x0B`; `0x07` appears in some offset records.
+    /// Record format marker. File records use [RECORD_FORMAT_MARKER], see [OffsetRecord] for
+    /// records using [OFFSET_FORMAT_MARKER].
     pub unk01: u8,
     /// Magic number
     pub magic: u16,
-    /// Per-entry identifier generated by the writer, likely inode or backup catalog metadata.
+    /// Checksum of header and name in the lower 16 bits and the link count (`nlink`) of the file
+    /// in the upper 16 bits, see [RecordHeader::link_count].
     pub unk04: u32,
-    /// Directory identifier / parent linkage field.
+    /// Inode number (`ino`) of the file, see [RecordHeader::inode].
     ///
-    /// Sampled directories carry descending negative values (`0xFFFF_FFFF`, `0xFFFF_FFFE`, ...),
-    /// while regular files use 0.
+    /// Backups by name store 0 for files and number directories down from `0xFFFF_FFFF`.
     pub unk08: u32,
     /// File mode (rwx...) as bit represntation
     pub mode: u32,
@@ -118,7 +229,7 @@ pub struct RecordHeader {
     pub uid: u32,
     /// Group ID number of the file
     pub gid: u32,
-    /// File size
+    /// File size. Lower 32 bits, see [RecordHeader::file_size].
     pub size: u32,
     /// Last access timestamp of the file.
     pub atime: u32,
@@ -126,47 +237,95 @@ pub struct RecordHeader {
     pub mtime: u32,
     /// Inode status-change timestamp (`ctime`).
     pub time24: u32,
-    /// Archive-wide filesystem/device metadata word.
+    /// Major number of the device holding the file, see [RecordHeader::device].
     ///
-    /// This is constant across all records in sampled archives but differs between archives.
+    /// This is constant across all records of an archive of one file system.
     pub unk28: u32,
-    /// Archive-wide filesystem/device metadata word.
-    ///
-    /// This is constant across all records in sampled archives but differs between archives.
+    /// Minor number of the device holding the file, see [RecordHeader::device].
     pub unk2_c: u32,
-    /// Reserved, observed as 0.
+    /// Reserved, observed as 0. Assumed to be the upper 32 bits of the file size, see
+    /// [RecordHeader::file_size].
     pub unk30: u32,
     /// Reserved, observed as 0.
     pub unk34: u32,
-    /// Stored payload size in the archive.
+    /// Stored payload size in the archive. Lower 32 bits, see [RecordHeader::payload_size].
     ///
     /// Equals `size` for raw records and is smaller when `magic == HUFFMAN_MAGIC`.
     pub compressed_size: u32,
-    /// Reserved, observed as 0.
+    /// Reserved, observed as 0. Assumed to be the upper 32 bits of the stored payload size, see
+    /// [RecordHeader::payload_size].
     pub unk3_c: u32,
 }
 
 impl RecordHeader {
-    /// Returns the entry subtype byte emitted by the AIX backup writer.
+    /// Returns the raw first byte of the header, the length of header and name in 8-byte units.
     pub fn entry_subtype(&self) -> u8 {
         self.unk00
     }
 
+    /// Returns the length of the header and the name in bytes.
+    pub fn header_len(&self) -> usize {
+        usize::from(self.unk00) * 8
+    }
+
     /// Returns the record format marker.
     pub fn format_marker(&self) -> u8 {
         self.unk01
     }
 
-    /// Returns the per-entry identifier written into the record header.
+    /// Returns the raw word holding checksum and link count, see [RecordHeader::link_count].
     pub fn entry_id(&self) -> u32 {
         self.unk04
     }
 
+    /// Returns the checksum bits of header and name validated by AIX.
+    pub fn stored_checksum(&self) -> u16 {
+        (self.unk04 & 0xFFFF) as u16
+    }
+
+    /// Returns the amount of hard links to the file. Directories count their subdirectories.
+    pub fn link_count(&self) -> u16 {
+        (self.unk04 >> 16) as u16
+    }
+
+    /// Returns the inode number of the file on the backed up file system.
+    pub fn inode(&self) -> u32 {
+        self.unk08
+    }
+
     /// Returns the directory identifier / parent linkage field.
     pub fn directory_id(&self) -> u32 {
         self.unk08
     }
 
+    /// Returns the file size including the upper 32 bits of large files.
+    ///
+    /// The large file layout is a guess which isn't verified against an archive larger than 4 GiB
+    /// or the AIX headers: `unk30` is assumed to hold the upper 32 bits. It is 0 in all sampled
+    /// archives, so smaller files aren't affected.
+    pub fn file_size(&self) -> u64 {
+        (u64::from(self.unk30) << 32) | u64::from(self.size)
+    }
+
+    /// Sets the file size. Sizes above 4 GiB use the large file fields.
+    pub fn set_file_size(&mut self, size: u64) {
+        self.size = size as u32;
+        self.unk30 = (size >> 32) as u32;
+    }
+
+    /// Returns the stored payload size including the upper 32 bits of large payloads.
+    ///
+    /// Like [RecordHeader::file_size], this assumes `unk3_c` holds the upper 32 bits.
+    pub fn payload_size(&self) -> u64 {
+        (u64::from(self.unk3_c) << 32) | u64::from(self.compressed_size)
+    }
+
+    /// Sets the stored payload size. Sizes above 4 GiB use the large file fields.
+    pub fn set_payload_size(&mut self, size: u64) {
+        self.compressed_size = size as u32;
+        self.unk3_c = (size >> 32) as u32;
+    }
+
     /// Returns the inode status-change timestamp (`ctime`).
     pub fn change_time(&self) -> u32 {
         self.time24
@@ -181,6 +340,30 @@ impl RecordHeader {
     pub fn archive_metadata_word_2(&self) -> u32 {
         self.unk2_c
     }
+
+    /// Returns the device holding the file, made of the metadata words.
+    pub fn device(&self) -> DeviceId {
+        DeviceId {
+            major: self.unk28,
+            minor: self.unk2_c,
+        }
+    }
+}
+
+/// Identifier of the device holding a file, like `st_dev` of `stat`.
+///
+/// AIX numbers the logical volumes of a volume group with the same major number, e.g. `/home` on
+/// `hd1` of rootvg is `10,8`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub struct DeviceId {
+    pub major: u32,
+    pub minor: u32,
+}
+
+impl Display for DeviceId {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{},{}", self.major, self.minor)
+    }
 }
 
 impl Default for RecordHeader {
@@ -208,33 +391,36 @@ impl Default for RecordHeader {
     }
 }
 
-/// Read string from stream until NULL.
-pub(crate) fn read_aligned_string<R: ?Sized + Read>(reader: &mut R) -> Result<String> {
+/// Read the bytes of a name from stream until NULL. The name is stored in 8-byte units.
+///
+/// The bytes are returned unchanged, as names may be encoded in any code page. Data behind a
+/// newline, tab, vertical tab or delete marker isn't part of the name.
+pub(crate) fn read_aligned_name<R: ?Sized + Read>(reader: &mut R) -> Result<Vec<u8>> {
     let mut result: Vec<u8> = vec![];
     loop {
         let mut data = [0; 8];
         let len = reader.read(&mut data)?;
         if len == 0 {
-            let s = String::from_utf8_lossy(&result);
-            return Ok(first_segment(&s));
+            return Ok(first_segment(result));
         }
         for c in data {
             if c == 0 {
-                let s = String::from_utf8_lossy(&result);
-                return Ok(first_segment(&s));
+                return Ok(first_segment(result));
             }
             result.push(c);
         }
     }
 }
 
-/// Get the first segment of a string until a newline, tab, or vertical tab.
-fn first_segment(text: &str) -> String {
-    if let Some(index) = text.find(|c| matches!(c, '\n' | '\t' | '\x0B' | '\x7F')) {
-        text[..index].to_string()
-    } else {
-        text.to_string()
+/// Get the first segment of a name until a newline, tab, vertical tab or delete marker.
+fn first_segment(mut name: Vec<u8>) -> Vec<u8> {
+    if let Some(index) = name
+        .iter()
+        .position(|c| matches!(c, b'\n' | b'\t' | b'\x0B' | b'\x7F'))
+    {
+        name.truncate(index);
     }
+    name
 }
 
 #[cfg(test)]
@@ -244,47 +430,55 @@ mod tests {
     use super::*;
 
     #[test]
-    fn read_aligned_string_default() {
+    fn read_aligned_name_default() {
         let mut reader = Cursor::new([97, 98, 99, 0, 1, 2, 3, 4]);
-        let result = read_aligned_string(&mut reader).expect("Could not read aligned string.");
-        assert_eq!(result, "abc");
+        let result = read_aligned_name(&mut reader).expect("Could not read aligned name.");
+        assert_eq!(result, b"abc");
     }
 
     #[test]
-    fn read_aligned_string_double() {
+    fn read_aligned_name_double() {
         let mut reader = Cursor::new([97, 98, 99, 0, 1, 2, 3, 4, 97, 98, 99, 0, 1, 2, 3, 4]);
-        let result = read_aligned_string(&mut reader).expect("Could not read aligned string.");
-        assert_eq!(result, "abc");
+        let result = read_aligned_name(&mut reader).expect("Could not read aligned name.");
+        assert_eq!(result, b"abc");
     }
 
     #[test]
-    fn read_aligned_string_long() {
+    fn read_aligned_name_long() {
         let mut reader = Cursor::new([
             97, 98, 99, 100, 101, 102, 103, 104, 97, 98, 99, 0, 1, 2, 3, 4,
         ]);
-        let result = read_aligned_string(&mut reader).expect("Could not read aligned string.");
-        assert_eq!(result, "abcdefghabc");
+        let result = read_aligned_name(&mut reader).expect("Could not read aligned name.");
+        assert_eq!(result, b"abcdefghabc");
     }
 
     #[test]
-    fn read_aligned_string_no_null() {
+    fn read_aligned_name_no_null() {
         let mut reader = Cursor::new([97, 98, 99, 1, 1, 2, 3, 4]);
-        let result = read_aligned_string(&mut reader).expect("Could not read aligned string.");
-        assert_eq!(result, "abc\u{1}\u{1}\u{2}\u{3}\u{4}");
+        let result = read_aligned_name(&mut reader).expect("Could not read aligned name.");
+        assert_eq!(result, b"abc\x01\x01\x02\x03\x04");
     }
 
     #[test]
-    fn read_aligned_string_stops_at_delete_marker() {
+    fn read_aligned_name_stops_at_delete_marker() {
         let mut reader = Cursor::new([97, 99, 108, 47, 127, 127, 0, 0]);
-        let result = read_aligned_string(&mut reader).expect("Could not read aligned string.");
-        assert_eq!(result, "acl/");
+        let result = read_aligned_name(&mut reader).expect("Could not read aligned name.");
+        assert_eq!(result, b"acl/");
     }
 
     #[test]
-    fn read_aligned_string_no_8byte() {
+    fn read_aligned_name_no_8byte() {
         let mut reader = Cursor::new([97, 98, 99, 1, 1, 2, 3]);
-        let result = read_aligned_string(&mut reader).expect("Could not read aligned string.");
-        assert_eq!(result, "abc\u{1}\u{1}\u{2}\u{3}");
+        let result = read_aligned_name(&mut reader).expect("Could not read aligned name.");
+        assert_eq!(result, b"abc\x01\x01\x02\x03");
+    }
+
+    #[test]
+    fn read_aligned_name_keeps_legacy_code_page() {
+        // "café" in ISO8859-1
+        let mut reader = Cursor::new([99, 97, 102, 0xE9, 0, 0, 0, 0]);
+        let result = read_aligned_name(&mut reader).expect("Could not read aligned name.");
+        assert_eq!(result, b"caf\xE9");
     }
 
     #[test]
@@ -301,6 +495,30 @@ mod tests {
         assert_eq!(header.format_version(), 100);
     }
 
+    #[test]
+    fn file_header_string_and_date_accessors() {
+        let header = FileHeader {
+            current_date: 0x6654_5ab2,
+            starting_date: 0x6654_5a00,
+            disk_name: *b"by name\0",
+            filesystem_name: *b"/dev/hd4",
+            username: *b" root \0\0",
+            ..unsafe { std::mem::zeroed() }
+        };
+
+        assert_eq!(header.disk_name(), "by name");
+        assert_eq!(header.filesystem_name(), "/dev/hd4");
+        assert_eq!(header.username(), "root");
+        assert_eq!(
+            header.current_date().to_rfc3339(),
+            "2024-05-27T10:04:34+00:00"
+        );
+        assert_eq!(
+            header.starting_date().to_rfc3339(),
+            "2024-05-27T10:01:36+00:00"
+        );
+    }
+
     #[test]
     fn compute_file_header_checksum_matches_sample_header() {
         let archive = include_bytes!("../../resources/test/test.bff");
@@ -328,5 +546,29 @@ mod tests {
         assert_eq!(header.change_time(), 1_716_804_102);
         assert_eq!(header.archive_metadata_word_1(), 0x24);
         assert_eq!(header.archive_metadata_word_2(), 0x01);
+        assert_eq!(header.header_len(), 120);
+        assert_eq!(header.stored_checksum(), 1234);
+        assert_eq!(header.link_count(), 0);
+        assert_eq!(header.inode(), u32::MAX);
+        assert_eq!(
+            header.device(),
+            DeviceId {
+                major: 0x24,
+                minor: 0x01
+            }
+        );
+        assert_eq!(header.device().to_string(), "36,1");
+    }
+
+    #[test]
+    fn record_header_large_file_sizes() {
+        let mut header = RecordHeader::default();
+        header.set_file_size(0x1_2345_6789);
+        header.set_payload_size(0x2_0000_0001);
+
+        assert_eq!((header.size, header.unk30), (0x2345_6789, 1));
+        assert_eq!((header.compressed_size, header.unk3_c), (1, 2));
+        assert_eq!(header.file_size(), 0x1_2345_6789);
+        assert_eq!(header.payload_size(), 0x2_0000_0001);
     }
 }
@@ -0,0 +1,206 @@
+//! Carving BFF archives out of arbitrary data
+//!
+//! Firmware images and disk dumps may contain archives at any offset. Every occurrence of
+//! [FILE_MAGIC](crate::bff::FILE_MAGIC) is a candidate. A candidate is accepted if its header has
+//! a valid checksum and its first record can be parsed. A header without checksum can't be
+//! verified, so its first two records have to be parsed.
+
+use std::io::{Read, Seek, SeekFrom};
+
+use crate::{
+    Result,
+    archive::{Archive, read_archive_extent, read_file_header, read_next_record},
+    stream::OffsetReader,
+    tape::find_magic,
+};
+
+/// An archive found in arbitrary data.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CarvedArchive {
+    /// Position of the archive in the data
+    pub offset: u64,
+    /// Length of the archive up to the end of its end of archive record
+    pub len: u64,
+}
+
+impl CarvedArchive {
+    /// Opens the archive in `reader` to read or extract its files.
+    pub fn open<R: Read + Seek>(&self, reader: R) -> Result<Archive<OffsetReader<R>>> {
+        Archive::scan(OffsetReader::new(reader, self.offset, Some(self.len))?)
+    }
+}
+
+/// Find all archives in `reader`.
+///
+/// Archives are returned in order of their offset. Archives embedded in the payload of another
+/// archive are part of the outer archive and not returned separately. An archive without end of
+/// archive record extends to the end of the data.
+pub fn find_archives<R: Read + Seek>(reader: &mut R) -> Result<Vec<CarvedArchive>> {
+    let mut archives = vec![];
+    let mut position = 0;
+    while let Some(offset) = find_magic(reader, position, 1)? {
+        match archive_len(reader, offset) {
+            Ok(Some(len)) => {
+                archives.push(CarvedArchive { offset, len });
+                position = offset + len.max(1);
+            }
+            // Archives already found are kept if a later candidate fails to read.
+            Ok(None) | Err(_) => position = offset + 1,
+        }
+    }
+    Ok(archives)
+}
+
+/// Validate the candidate at `offset` and return the length of its archive.
+///
+/// Returns `None` if the candidate isn't an archive.
+fn archive_len<R: Read + Seek>(reader: &mut R, offset: u64) -> Result<Option<u64>> {
+    let mut archive = OffsetReader::new(&mut *reader, offset, None)?;
+    let Ok(header) = read_file_header(&mut archive) else {
+        return Ok(None);
+    };
+    let valid = match read_next_record(&mut archive) {
+        // Any data following the magic number passes a missing checksum.
+        Ok(Some(_)) if header.stored_checksum() == 0 => read_next_record(&mut archive).is_ok(),
+        Ok(_) => header.stored_checksum() != 0,
+        Err(_) => false,
+    };
+    if !valid {
+        return Ok(None);
+    }
+    archive.seek(SeekFrom::Start(0))?;
+    Ok(Some(read_archive_extent(&mut archive)?.len))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::{self, Cursor};
+
+    use super::*;
+    use crate::{
+        Error,
+        bff::{FILE_MAGIC, RecordHeader},
+        writer::archive_with_files,
+    };
+
+    /// A reader failing to read at `fail_at`, like a bad sector.
+    struct FailingReader {
+        inner: Cursor<Vec<u8>>,
+        fail_at: u64,
+    }
+
+    impl Read for FailingReader {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            if self.inner.position() == self.fail_at {
+                return Err(io::Error::other("bad sector"));
+            }
+            self.inner.read(buf)
+        }
+    }
+
+    impl Seek for FailingReader {
+        fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+            self.inner.seek(pos)
+        }
+    }
+
+    #[test]
+    fn finds_archives_at_arbitrary_offsets() {
+        let first = archive_with_files(&[("./first.txt", b"first")]);
+        let second = archive_with_files(&[("./second.txt", b"second")]);
+        let mut blob = vec![0x11; 13];
+        blob.extend(&first);
+        blob.extend([0x22; 70_001]);
+        blob.extend(&second);
+        blob.extend([0x33; 5]);
+
+        let archives = find_archives(&mut Cursor::new(&blob)).unwrap();
+
+        assert_eq!(
+            archives,
+            [
+                CarvedArchive {
+                    offset: 13,
+                    len: first.len() as u64
+                },
+                CarvedArchive {
+                    offset: 13 + first.len() as u64 + 70_001,
+                    len: second.len() as u64
+                },
+            ]
+        );
+
+        let mut archive = archives[1].open(Cursor::new(&blob)).unwrap();
+        let mut content = String::new();
+        archive
+            .file("./second.txt")
+            .unwrap()
+            .unwrap()
+            .read_to_string(&mut content)
+            .unwrap();
+        assert_eq!(content, "second");
+    }
+
+    #[test]
+    fn ignores_invalid_candidates() {
+        let archive = archive_with_files(&[("./file.txt", b"content")]);
+        // A bare magic number, a header with a broken checksum and a header without records
+        let mut blob = FILE_MAGIC.to_le_bytes().to_vec();
+        let mut broken = archive.clone();
+        broken[20] ^= 1;
+        blob.extend(&broken[..200]);
+        blob.extend(&archive[..72]);
+        blob.extend([0xFF; 64]);
+
+        assert!(find_archives(&mut Cursor::new(&blob)).unwrap().is_empty());
+        assert!(matches!(
+            read_file_header(&mut broken.as_slice()),
+            Err(Error::InvalidFileChecksum { .. })
+        ));
+    }
+
+    #[test]
+    fn requires_second_record_without_checksum() {
+        let mut archive = archive_with_files(&[("./file.txt", b"content")]);
+        archive[4..6].fill(0);
+        let first_record_end = archive.len() - 8;
+        let mut blob = archive[..first_record_end].to_vec();
+        blob.extend([0xFF; 64]);
+        blob.extend(&archive);
+
+        assert_eq!(
+            find_archives(&mut Cursor::new(&blob)).unwrap(),
+            [CarvedArchive {
+                offset: first_record_end as u64 + 64,
+                len: archive.len() as u64
+            }]
+        );
+    }
+
+    #[test]
+    fn keeps_archives_found_before_failing_candidate() {
+        let first = archive_with_files(&[("./first.txt", b"first")]);
+        let second = archive_with_files(&[("./a.txt", b"content"), ("./b.txt", b"content")]);
+        let mut blob = first.clone();
+        blob.extend(&second);
+        let fail_at = blob
+            .windows(7)
+            .position(|window| window == b"./b.txt")
+            .unwrap()
+            - std::mem::size_of::<RecordHeader>();
+
+        let archives = find_archives(&mut FailingReader {
+            inner: Cursor::new(blob),
+            fail_at: fail_at as u64,
+        })
+        .unwrap();
+
+        assert_eq!(
+            archives,
+            [CarvedArchive {
+                offset: 0,
+                len: first.len() as u64
+            }]
+        );
+    }
+}
@@ -0,0 +1,139 @@
+//! Character sets of record names
+//!
+//! Names are stored in the archive as bytes in the code page of the system the backup was created
+//! on. AIX systems commonly use ISO8859-1, ISO8859-15 or, on Japanese systems, IBM-943 (Shift-JIS)
+//! and IBM-eucJP. A [Charset] converts such names to UTF-8.
+
+use std::{fmt::Display, str::FromStr};
+
+use encoding_rs::{EUC_JP, Encoding, ISO_8859_15, SHIFT_JIS};
+
+use crate::Error;
+
+/// Character set of the names stored in an archive.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Charset {
+    /// ISO8859-1 (Latin-1)
+    Iso8859_1,
+    /// ISO8859-15 (Latin-9)
+    Iso8859_15,
+    /// IBM-943, the AIX variant of Shift-JIS
+    Ibm943,
+    /// IBM-eucJP
+    IbmEucJp,
+    /// UTF-8
+    Utf8,
+}
+
+impl Charset {
+    /// All supported character sets.
+    pub const ALL: [Charset; 5] = [
+        Self::Iso8859_1,
+        Self::Iso8859_15,
+        Self::Ibm943,
+        Self::IbmEucJp,
+        Self::Utf8,
+    ];
+
+    /// Decode `bytes` to a string.
+    ///
+    /// Returns [None] if the bytes aren't valid in this character set.
+    pub fn decode(self, bytes: &[u8]) -> Option<String> {
+        match self {
+            // Every byte is a character of the same code point.
+            Self::Iso8859_1 => Some(bytes.iter().map(|&c| char::from(c)).collect()),
+            Self::Iso8859_15 => decode_with(ISO_8859_15, bytes),
+            Self::Ibm943 => decode_with(SHIFT_JIS, bytes),
+            Self::IbmEucJp => decode_with(EUC_JP, bytes),
+            Self::Utf8 => String::from_utf8(bytes.to_vec()).ok(),
+        }
+    }
+
+    /// Returns the name of the character set as used by AIX.
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Iso8859_1 => "ISO8859-1",
+            Self::Iso8859_15 => "ISO8859-15",
+            Self::Ibm943 => "IBM-943",
+            Self::IbmEucJp => "IBM-eucJP",
+            Self::Utf8 => "UTF-8",
+        }
+    }
+
+    /// Returns further names the character set is known by.
+    fn aliases(self) -> &'static [&'static str] {
+        match self {
+            Self::Iso8859_1 => &["ISO-8859-1", "latin1"],
+            Self::Iso8859_15 => &["ISO-8859-15", "latin9"],
+            Self::Ibm943 => &["Shift-JIS", "Shift_JIS", "SJIS"],
+            Self::IbmEucJp => &["EUC-JP", "eucJP"],
+            Self::Utf8 => &["UTF8"],
+        }
+    }
+}
+
+impl Display for Charset {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.name())
+    }
+}
+
+impl FromStr for Charset {
+    type Err = Error;
+
+    /// Parse the name of a character set, ignoring the case.
+    fn from_str(name: &str) -> Result<Self, Self::Err> {
+        Self::ALL
+            .into_iter()
+            .find(|charset| {
+                std::iter::once(&charset.name())
+                    .chain(charset.aliases())
+                    .any(|alias| alias.eq_ignore_ascii_case(name))
+            })
+            .ok_or_else(|| Error::UnknownCharset(name.to_string()))
+    }
+}
+
+/// Decode `bytes` by `encoding` without replacing malformed sequences.
+fn decode_with(encoding: &'static Encoding, bytes: &[u8]) -> Option<String> {
+    encoding
+        .decode_without_bom_handling_and_without_replacement(bytes)
+        .map(|name| name.into_owned())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn decodes_legacy_code_pages() {
+        assert_eq!(Charset::Iso8859_1.decode(b"caf\xE9").unwrap(), "café");
+        assert_eq!(Charset::Iso8859_15.decode(b"\xA4uro").unwrap(), "€uro");
+        // "日本" in IBM-943 and IBM-eucJP
+        assert_eq!(Charset::Ibm943.decode(b"\x93\xfa\x96\x7b").unwrap(), "日本");
+        assert_eq!(
+            Charset::IbmEucJp.decode(b"\xc6\xfc\xcb\xdc").unwrap(),
+            "日本"
+        );
+        assert_eq!(Charset::Utf8.decode("日本".as_bytes()).unwrap(), "日本");
+    }
+
+    #[test]
+    fn rejects_invalid_names() {
+        assert!(Charset::Utf8.decode(b"caf\xE9").is_none());
+        assert!(Charset::Ibm943.decode(b"\x93").is_none());
+    }
+
+    #[test]
+    fn parses_names_and_aliases() {
+        for charset in Charset::ALL {
+            assert_eq!(charset.name().parse::<Charset>().unwrap(), charset);
+        }
+        assert_eq!("shift-jis".parse::<Charset>().unwrap(), Charset::Ibm943);
+        assert_eq!("utf-8".parse::<Charset>().unwrap(), Charset::Utf8);
+        assert!(matches!(
+            "EBCDIC".parse::<Charset>(),
+            Err(Error::UnknownCharset(name)) if name == "EBCDIC"
+        ));
+    }
+}
@@ -0,0 +1,213 @@
+//! Detection and decoding of compressed files wrapping BFF archives
+//!
+//! Archives are often distributed as `.bff.gz`, `.bff.bz2` or `.bff.Z` files. The wrapper is
+//! detected by its magic number and decoded while reading. The decoded data can't be seeked, so it
+//! either has to be read by [crate::archive::RecordIter] or copied to a seekable location first.
+
+use std::{
+    fmt::Display,
+    io::{Cursor, Read, Seek, SeekFrom},
+};
+
+use bzip2::read::MultiBzDecoder;
+use flate2::read::MultiGzDecoder;
+
+use crate::{
+    Result,
+    lzw::{LZW_MAGIC, LzwDecoder},
+    pack::{PACK_MAGIC, PackReader},
+};
+
+/// All gzip files start with these bytes.
+const GZIP_MAGIC: [u8; 2] = [0x1F, 0x8B];
+/// All bzip2 files start with these bytes.
+const BZIP2_MAGIC: [u8; 3] = *b"BZh";
+/// Amount of bytes needed to detect every supported compression.
+const MAGIC_LEN: usize = 3;
+
+/// Compression formats detected in front of an archive.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Compression {
+    /// gzip (.gz) files
+    Gzip,
+    /// bzip2 (.bz2) files
+    Bzip2,
+    /// Files of the Unix `compress` command (.Z)
+    Lzw,
+    /// Files of the Unix `pack` command (.z)
+    Pack,
+}
+
+impl Compression {
+    /// Detect the compression by the magic number at the start of `data`.
+    ///
+    /// Returns `None` if the data doesn't start with a known magic number.
+    pub fn from_magic(data: &[u8]) -> Option<Self> {
+        if data.starts_with(&GZIP_MAGIC) {
+            Some(Self::Gzip)
+        } else if data.starts_with(&BZIP2_MAGIC) {
+            Some(Self::Bzip2)
+        } else if data.starts_with(&LZW_MAGIC) {
+            Some(Self::Lzw)
+        } else if data.starts_with(&PACK_MAGIC) {
+            Some(Self::Pack)
+        } else {
+            None
+        }
+    }
+
+    /// Detect the compression of the data at the current position of `reader`.
+    ///
+    /// The reader is positioned back to where it was afterwards.
+    pub fn detect<R: Read + Seek>(reader: &mut R) -> Result<Option<Self>> {
+        let start = reader.stream_position()?;
+        let magic = read_magic(reader)?;
+        reader.seek(SeekFrom::Start(start))?;
+        Ok(Self::from_magic(&magic))
+    }
+
+    /// Returns a reader decoding `reader`, which has to start with the magic number of this
+    /// compression.
+    pub fn decoder<'a, R: Read + 'a>(self, reader: R) -> Result<Box<dyn Read + 'a>> {
+        Ok(match self {
+            Self::Gzip => Box::new(MultiGzDecoder::new(reader)),
+            Self::Bzip2 => Box::new(MultiBzDecoder::new(reader)),
+            Self::Lzw => Box::new(LzwDecoder::new(reader)?),
+            Self::Pack => Box::new(PackReader::new(reader)?),
+        })
+    }
+}
+
+impl Display for Compression {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Gzip => write!(f, "gzip"),
+            Self::Bzip2 => write!(f, "bzip2"),
+            Self::Lzw => write!(f, "compress"),
+            Self::Pack => write!(f, "pack"),
+        }
+    }
+}
+
+/// Returns a reader transparently decoding `reader` if it starts with the magic number of a
+/// supported compression. Otherwise the data is passed through unchanged.
+///
+/// This doesn't need [Seek], so it works with pipes too.
+pub fn decompress<'a, R: Read + 'a>(mut reader: R) -> Result<Box<dyn Read + 'a>> {
+    let magic = read_magic(&mut reader)?;
+    let compression = Compression::from_magic(&magic);
+    // The magic number was consumed already, so it is put in front of the remaining data again.
+    let reader = Cursor::new(magic).chain(reader);
+    match compression {
+        Some(compression) => compression.decoder(reader),
+        None => Ok(Box::new(reader)),
+    }
+}
+
+/// Read up to [MAGIC_LEN] bytes. Less bytes are returned only at the end of the data.
+fn read_magic<R: Read>(reader: &mut R) -> Result<Vec<u8>> {
+    let mut magic = Vec::with_capacity(MAGIC_LEN);
+    reader.take(MAGIC_LEN as u64).read_to_end(&mut magic)?;
+    Ok(magic)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        fs,
+        io::{Cursor, Read, Write},
+        path::PathBuf,
+    };
+
+    use super::*;
+    use crate::{archive::RecordIter, pack::PackWriter, stream::ForwardReader};
+
+    fn get_resources_path() -> PathBuf {
+        let mut dir = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+        dir.push("../resources/test");
+        dir
+    }
+
+    fn decompress_file(filename: &str) -> Vec<u8> {
+        let file = fs::File::open(get_resources_path().join(filename)).unwrap();
+        let mut result = vec![];
+        decompress(file).unwrap().read_to_end(&mut result).unwrap();
+        result
+    }
+
+    #[test]
+    fn detects_magic_numbers() {
+        assert_eq!(
+            Compression::from_magic(&[0x1F, 0x8B, 8]),
+            Some(Compression::Gzip)
+        );
+        assert_eq!(Compression::from_magic(b"BZh9"), Some(Compression::Bzip2));
+        assert_eq!(
+            Compression::from_magic(&[0x1F, 0x9D, 0x90]),
+            Some(Compression::Lzw)
+        );
+        assert_eq!(
+            Compression::from_magic(&[0x1F, 0x1E, 0]),
+            Some(Compression::Pack)
+        );
+        assert_eq!(Compression::from_magic(&[0x09, 0x00, 0x6B, 0xEA]), None);
+        assert_eq!(Compression::from_magic(&[0x1F]), None);
+    }
+
+    #[test]
+    fn detect_keeps_position() {
+        let mut reader = Cursor::new(fs::read(get_resources_path().join("test.bff.gz")).unwrap());
+
+        assert_eq!(
+            Compression::detect(&mut reader).unwrap(),
+            Some(Compression::Gzip)
+        );
+        assert_eq!(reader.position(), 0);
+    }
+
+    #[test]
+    fn decompress_wrapped_archives() {
+        let expected = fs::read(get_resources_path().join("test.bff")).unwrap();
+
+        for filename in ["test.bff.gz", "test.bff.bz2", "test.bff.Z", "test.bff"] {
+            assert_eq!(decompress_file(filename), expected, "{filename}");
+        }
+    }
+
+    #[test]
+    fn decompress_packed_archive() {
+        let expected = fs::read(get_resources_path().join("test.bff")).unwrap();
+        let mut writer = PackWriter::new(vec![]);
+        writer.write_all(&expected).unwrap();
+        let packed = writer.finish().unwrap();
+
+        let mut result = vec![];
+        decompress(packed.as_slice())
+            .unwrap()
+            .read_to_end(&mut result)
+            .unwrap();
+        assert_eq!(result, expected);
+    }
+
+    #[test]
+    fn decompress_short_input() {
+        let mut result = vec![];
+        decompress([0x1F].as_slice())
+            .unwrap()
+            .read_to_end(&mut result)
+            .unwrap();
+
+        assert_eq!(result, [0x1F]);
+    }
+
+    #[test]
+    fn stream_records_of_wrapped_archive() {
+        let file = fs::File::open(get_resources_path().join("test.bff.Z")).unwrap();
+        let records = RecordIter::scan(ForwardReader::new(decompress(file).unwrap()))
+            .unwrap()
+            .collect::<Result<Vec<_>>>()
+            .unwrap();
+
+        assert!(!records.is_empty());
+    }
+}
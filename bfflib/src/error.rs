@@ -1,6 +1,8 @@
 use std::fmt::Display;
 use std::path::PathBuf;
 
+use crate::charset::Charset;
+
 pub type Result<T> = core::result::Result<T, Error>;
 
 /// General error wrapping more specific errors.
@@ -13,6 +15,14 @@ pub enum Error {
     InvalidFileChecksum { stored: u16, computed: u16 },
     /// The file header checksum was invalid. The checksum could not be computed due to an invalid format.
     InvalidFileChecksumFormat,
+    /// The archive is a backup by inode, which can't be read.
+    UnsupportedByInodeBackup,
+    /// A packed file had an invalid magic number. Provides the magic number read.
+    InvalidPackMagic(u16),
+    /// A compressed (.Z) file had an invalid magic number. Provides the magic number read.
+    InvalidLzwMagic(u16),
+    /// A compressed (.Z) file had an invalid header or contained an undefined code.
+    InvalidLzwData,
     /// An record had an invalid magic number. Provides the magic number read.
     InvalidRecordMagic(u16),
     /// The record was invalid. This also may indicate some unsupported features.
@@ -25,16 +35,37 @@ pub enum Error {
     InvalidLevelIndex,
     /// The decoding table of the record is invalid.
     InvalidTreelevel,
-    /// File size is bigger than 4 GiB. Actually the lib doesn't support larger files.
+    /// A compressed record didn't decode to the size stored in its header.
+    DecodedSizeMismatch { expected: u64, actual: u64 },
+    /// Data is bigger than 4 GiB, which a packed file or a written record can't store.
     FileToBig,
     /// A filename was not found in archive
     FileNotFound,
+    /// A tape image contains less archives than requested. Provides the requested archive number.
+    ArchiveNotFound(usize),
+    /// An image doesn't contain a mksysb or savevg backup.
+    BackupNotFound,
+    /// Carving found no archive in the data.
+    NoArchiveFound,
+    /// Validating an archive found errors. Provides the amount of errors.
+    ValidationFailed(usize),
     /// A record path would escape the requested extraction root.
     InvalidExtractionPath(PathBuf),
+    /// A record path can't be represented in a BFF archive.
+    InvalidFilename(PathBuf),
     /// A symbolic link record was missing its link target.
     MissingSymlinkTarget(PathBuf),
+    /// A volume doesn't belong to the same backup as the first volume. Provides the volume number.
+    VolumeMismatch(usize),
+    /// Volumes are given in the wrong order. Provides the position of the volume in the given
+    /// order and the volume number stored in its header.
+    VolumeOutOfOrder { position: usize, number: u16 },
     /// A record contains unsupported file type
     UnsupportedFileType(String),
+    /// A character set name is unknown. Provides the name.
+    UnknownCharset(String),
+    /// A record name isn't valid in the character set it should be converted from.
+    InvalidNameEncoding { name: PathBuf, charset: Charset },
 
     // Extraction errors
     /// File system entry mode could not be set. Typically should contain a `std::io::error`.
@@ -57,6 +88,10 @@ impl Display for Error {
         match self {
             // Read errors
             BadSymbolTable => write!(f, "Invalid file format: Bad symbol table."),
+            DecodedSizeMismatch { expected, actual } => write!(
+                f,
+                "Invalid file format: Compressed data decoded to {actual} bytes instead of {expected} bytes."
+            ),
             EmptyFilename => {
                 write!(f, "Record having an empty filename will be skipped.")
             }
@@ -73,9 +108,22 @@ impl Display for Error {
                 f,
                 "Invalid file format: File header checksum could not be computed due to an invalid format."
             ),
+            UnsupportedByInodeBackup => write!(
+                f,
+                "The archive is a backup by inode. Only backups by name are supported."
+            ),
             InvalidLevelIndex => {
                 write!(f, "Invalid file format: Invalid level index found.")
             }
+            InvalidLzwData => write!(f, "Invalid file format: Compressed file is corrupt."),
+            InvalidLzwMagic(magic) => write!(
+                f,
+                "Invalid file format: Compressed file has an invalid magic number '{magic:#06x}'."
+            ),
+            InvalidPackMagic(magic) => write!(
+                f,
+                "Invalid file format: Packed file has an invalid magic number '{magic:#06x}'."
+            ),
             InvalidRecordMagic(magic) => write!(
                 f,
                 "Invalid file format: Record has an invalid magic number '{magic}'."
@@ -85,17 +133,42 @@ impl Display for Error {
                 write!(f, "Invalid file format: Invalid tree levels.")
             }
             FileNotFound => write!(f, "Filename wasn't found in archive."),
+            ArchiveNotFound(number) => write!(f, "Archive {number} wasn't found in the image."),
+            BackupNotFound => write!(f, "No mksysb or savevg backup was found in the image."),
+            NoArchiveFound => write!(f, "No archive was found in the data."),
+            ValidationFailed(count) => write!(f, "Archive validation found {count} error(s)."),
             InvalidExtractionPath(path) => write!(
                 f,
                 "Record path escapes extraction root and was rejected: {}",
                 path.display()
             ),
+            InvalidFilename(path) => {
+                write!(f, "Path can't be stored as record name: {}", path.display())
+            }
             MissingSymlinkTarget(path) => write!(
                 f,
                 "Symbolic link record is missing a link target: {}",
                 path.display()
             ),
+            VolumeMismatch(volume) => write!(
+                f,
+                "Volume {volume} doesn't belong to the same backup as the first volume."
+            ),
+            VolumeOutOfOrder { position, number } => write!(
+                f,
+                "Volume {position} is volume {number} of the backup. Volumes have to be given in order."
+            ),
             UnsupportedFileType(s) => write!(f, "The file type of the record is unsupported: {s}."),
+            UnknownCharset(name) => write!(
+                f,
+                "Unknown character set '{name}'. Supported are: {}.",
+                Charset::ALL.map(Charset::name).join(", ")
+            ),
+            InvalidNameEncoding { name, charset } => write!(
+                f,
+                "{}: Name isn't valid {charset} and is kept unconverted.",
+                name.display()
+            ),
 
             // Extraction errors
             ModeError(mode_error) => {
@@ -111,6 +184,7 @@ impl Display for Error {
 
 impl From<std::io::Error> for Error {
     fn from(value: std::io::Error) -> Self {
-        Error::IoError(value)
+        // Errors of this crate passed through `std::io::Read` implementations are unwrapped again.
+        value.downcast::<Error>().unwrap_or_else(Error::IoError)
     }
 }
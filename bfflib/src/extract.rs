@@ -1,25 +1,25 @@
 use std::{
-    fs::File,
-    io::{self, copy, BufWriter, Read, Seek, SeekFrom, Take},
+    fs::{self, File},
+    io::{self, BufWriter, Read, Seek, SeekFrom, copy},
     path::{Path, PathBuf},
 };
 
 use file_mode::FileType;
 #[cfg(unix)]
 use file_mode::ModePath;
-use filetime::{set_file_times, FileTime};
+use filetime::{FileTime, set_file_times};
 #[cfg(unix)]
 use std::os::unix::fs::chown;
 #[cfg(unix)]
 use std::os::unix::fs::symlink;
 
 use crate::{
+    Error, Result,
     archive::Record,
     attribute,
     bff::HUFFMAN_MAGIC,
     huffman::HuffmanDecoder,
-    util::{create_dir_all, create_parent_dir_all},
-    Error, Result,
+    util::{ReadSeek, create_dir_all, create_parent_dir_all},
 };
 
 #[derive(Debug)]
@@ -74,6 +74,11 @@ impl<R: Read + Seek> ArchiveSource<R> {
         open_record_reader(&mut self.reader, record, true)
     }
 
+    /// Returns the length of the archive.
+    pub(crate) fn len(&mut self) -> Result<u64> {
+        Ok(self.reader.seek(SeekFrom::End(0))?)
+    }
+
     pub(crate) fn read_text(&mut self, record: &Record) -> Result<Option<String>> {
         if !record
             .mode()
@@ -83,10 +88,9 @@ impl<R: Read + Seek> ArchiveSource<R> {
             return Ok(None);
         }
 
-        self.reader
-            .seek(SeekFrom::Start(record.file_position() as u64))?;
-        let mut take = (&mut self.reader as &mut dyn Read).take(record.compressed_size() as u64);
-        let mut buf = Vec::with_capacity(record.compressed_size() as usize);
+        self.reader.seek(SeekFrom::Start(record.file_position()))?;
+        let mut take = (&mut self.reader as &mut dyn Read).take(record.compressed_size());
+        let mut buf = Vec::new();
         take.read_to_end(&mut buf)?;
 
         Ok(String::from_utf8(buf).ok())
@@ -94,9 +98,12 @@ impl<R: Read + Seek> ArchiveSource<R> {
 }
 
 /// A reader to handle different file types
+///
+/// Both variants are seekable. Seeking a Huffman encoded record backwards restarts decoding at a
+/// checkpoint recorded while reading, so the first seek to a far position is the slowest one.
 pub enum RecordReader<'a> {
-    Raw(Take<&'a mut dyn Read>),
-    Huffman(HuffmanDecoder<Take<&'a mut dyn Read>>),
+    Raw(RecordData<'a>),
+    Huffman(Box<HuffmanDecoder<RecordData<'a>>>),
 }
 
 impl<'a> Read for RecordReader<'a> {
@@ -108,6 +115,77 @@ impl<'a> Read for RecordReader<'a> {
     }
 }
 
+impl<'a> Seek for RecordReader<'a> {
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        match self {
+            RecordReader::Raw(reader) => reader.seek(pos),
+            RecordReader::Huffman(reader) => reader.seek(pos),
+        }
+    }
+}
+
+/// A reader limited to the stored data of a record.
+///
+/// Positions are relative to the start of the record data.
+pub struct RecordData<'a> {
+    reader: &'a mut dyn ReadSeek,
+    start: u64,
+    len: u64,
+    position: u64,
+}
+
+impl<'a> RecordData<'a> {
+    fn new(reader: &'a mut dyn ReadSeek, start: u64, len: u64) -> io::Result<Self> {
+        reader.seek(SeekFrom::Start(start))?;
+        Ok(Self {
+            reader,
+            start,
+            len,
+            position: 0,
+        })
+    }
+
+    /// Returns the length of the stored data.
+    pub fn len(&self) -> u64 {
+        self.len
+    }
+
+    /// Returns `true` if the record has no stored data.
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+}
+
+impl<'a> Read for RecordData<'a> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let remaining = self.len.saturating_sub(self.position);
+        let max = buf.len().min(remaining.try_into().unwrap_or(usize::MAX));
+        let read = self.reader.read(&mut buf[..max])?;
+        self.position += read as u64;
+        Ok(read)
+    }
+}
+
+impl<'a> Seek for RecordData<'a> {
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        let position = match pos {
+            SeekFrom::Start(offset) => Some(offset),
+            SeekFrom::Current(offset) => self.position.checked_add_signed(offset),
+            SeekFrom::End(offset) => self.len.checked_add_signed(offset),
+        }
+        .filter(|position| position.checked_add(self.start).is_some())
+        .ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "invalid seek to a negative or overflowing position",
+            )
+        })?;
+        self.reader.seek(SeekFrom::Start(self.start + position))?;
+        self.position = position;
+        Ok(position)
+    }
+}
+
 /// Extract a single file to destination folder.
 pub(crate) fn extract_file<R: Read, D: AsRef<Path>>(reader: &mut R, destination: D) -> Result<()> {
     let writer = File::create(destination)?;
@@ -126,7 +204,10 @@ pub(crate) fn extract_record_with_attr<R: Read + Seek, D: AsRef<Path>>(
         Some(file_type) if file_type.is_regular_file() => {
             create_parent_dir_all(&destination)?;
             let mut reader = source.open(record)?.ok_or(Error::FileNotFound)?;
-            extract_file(&mut reader, &destination)
+            extract_file(&mut reader, &destination).inspect_err(|_| {
+                // Don't leave truncated or corrupted content behind.
+                let _ = fs::remove_file(&destination);
+            })
         }
         #[cfg(unix)]
         Some(file_type) if file_type.is_symbolic_link() => {
@@ -196,19 +277,21 @@ fn is_unsupported_filetype(filetype: FileType) -> bool {
     unsup
 }
 
-fn open_record_reader<'a, R: Read + Seek>(
+pub(crate) fn open_record_reader<'a, R: Read + Seek>(
     reader: &'a mut R,
     record: &Record,
     raw: bool,
 ) -> Result<Option<RecordReader<'a>>> {
     match record.mode().file_type() {
         Some(file_type) if file_type.is_regular_file() => {
-            reader.seek(SeekFrom::Start(record.file_position() as u64))?;
-            let take = (reader as &mut dyn Read).take(record.compressed_size() as u64);
+            let data = RecordData::new(reader, record.file_position(), record.compressed_size())?;
             let record_reader = if record.magic() == HUFFMAN_MAGIC && !raw {
-                RecordReader::Huffman(HuffmanDecoder::new(take)?)
+                RecordReader::Huffman(Box::new(HuffmanDecoder::with_expected_len(
+                    data,
+                    record.size(),
+                )?))
             } else {
-                RecordReader::Raw(take)
+                RecordReader::Raw(data)
             };
             Ok(Some(record_reader))
         }
@@ -1,28 +1,99 @@
-//! Decoding of compressed BFF record data
+//! Decoding and encoding of compressed BFF record data
 
 use crate::{Error, Result};
 use std::{
-    cmp::min,
-    collections::VecDeque,
-    io::{ErrorKind, Read},
+    cmp::Reverse,
+    collections::BinaryHeap,
+    io::{ErrorKind, Read, Seek, SeekFrom, Write},
 };
 
+/// Longest code of the Unix `pack` format. This limits the amount of tree levels.
+const MAX_CODE_LENGTH: usize = 24;
+/// Index of the end of stream marker in the symbol alphabet.
+const EOF_SYMBOL: usize = 256;
+
+/// Amount of input bits resolved by a single lookup in the decoding table.
+const TABLE_BITS: u32 = 10;
+/// Mask selecting [TABLE_BITS] bits.
+const TABLE_MASK: usize = (1 << TABLE_BITS) - 1;
+/// Size of the blocks read from the compressed input.
+const INPUT_BUFFER_SIZE: usize = 64 * 1024;
+/// Amount of decoded bytes between two checkpoints used for seeking.
+const CHECKPOINT_INTERVAL: u64 = 256 * 1024;
+
 /// A decoder for BFF file contents which is Huffman encoded.
+///
+/// Codes up to [TABLE_BITS] bits are decoded by a single table lookup. Longer codes continue
+/// bit by bit from the tree node the lookup ended at.
+///
+/// If the reader implements [Seek], the decoder implements [Seek] too. While decoding, the input
+/// position is recorded every [CHECKPOINT_INTERVAL] bytes. Seeking backwards restarts at the
+/// closest checkpoint, seeking behind the decoded data decodes and discards the data in between.
 pub struct HuffmanDecoder<R> {
     /// Source reader containing compressed data
     reader: R,
-    code: u8,
-    level: usize,
     /// Amount of Huffman tree levels
     treelevels: usize,
-    inodesin: Vec<u8>,
-    /// Symbols read
-    symbolsin: Vec<u8>,
+    /// Amount of internal nodes per tree level
+    inodesin: Vec<usize>,
+    /// Amount of leaves per tree level
+    symbolsin: Vec<usize>,
     /// Huffman tree
     tree: Vec<Vec<u8>>,
     treelens: Vec<usize>,
     symbol_size: usize,
-    offset_buf: VecDeque<u8>,
+    /// Decoding table indexed by the next [TABLE_BITS] input bits
+    table: Box<[TableEntry; 1 << TABLE_BITS]>,
+    /// Block of compressed input
+    input: Box<[u8]>,
+    /// Position of the next unused byte in `input`
+    input_pos: usize,
+    /// Amount of valid bytes in `input`
+    input_len: usize,
+    /// Input bits not decoded yet. The next bit is the highest of the lowest `bit_count` bits.
+    bit_buf: u64,
+    bit_count: u32,
+    /// Amount of compressed bytes read after the header
+    consumed: u64,
+    /// Input positions recorded every [CHECKPOINT_INTERVAL] decoded bytes
+    checkpoints: Vec<Checkpoint>,
+    /// Amount of bytes decoded so far
+    decoded: u64,
+    /// Amount of bytes the stream has to decode to, if known
+    expected_len: Option<u64>,
+    /// The end of the stream was reached
+    finished: bool,
+}
+
+/// A position in the stream decoding can resume at.
+#[derive(Clone, Copy)]
+struct Checkpoint {
+    /// Amount of bytes decoded before this position
+    decoded: u64,
+    /// Position of the next code in bits after the header
+    bit_position: u64,
+}
+
+/// Result of decoding a single code.
+enum Decoded {
+    Symbol(u8),
+    /// The end of stream code was found.
+    EndOfStream,
+    /// The input ended without an end of stream code.
+    EndOfInput,
+}
+
+/// Entry of the decoding table.
+#[derive(Clone, Copy)]
+enum TableEntry {
+    /// A symbol and the length of its code
+    Symbol(u8, u32),
+    /// The end of stream code and its length
+    EndOfStream(u32),
+    /// No code ends within the table bits. Decoding continues at this code and tree level.
+    Continue(u8, usize),
+    /// The table bits are invalid. Decoding bit by bit reports the error.
+    Invalid,
 }
 
 impl<R: Read> HuffmanDecoder<R> {
@@ -30,135 +101,637 @@ impl<R: Read> HuffmanDecoder<R> {
     ///
     /// It will read the bFF file header. If this fails or the header is invalid, an error will be returned.
     pub fn new(reader: R) -> Result<Self> {
+        Self::from_reader(reader, None)
+    }
+
+    /// Create a new instance of `HuffmanDecoder` which has to decode to exactly `expected_len` bytes.
+    ///
+    /// Reading returns [Error::DecodedSizeMismatch] if the stream ends early or contains more data.
+    pub fn with_expected_len(reader: R, expected_len: u64) -> Result<Self> {
+        Self::from_reader(reader, Some(expected_len))
+    }
+
+    fn from_reader(reader: R, expected_len: Option<u64>) -> Result<Self> {
         let mut decoder = HuffmanDecoder {
             reader,
-            code: 0,
-            level: 0,
             treelevels: 0,
             inodesin: vec![],
             symbolsin: vec![],
             tree: vec![],
             treelens: vec![],
             symbol_size: 0,
-            offset_buf: VecDeque::with_capacity(8),
+            table: Box::new([TableEntry::Invalid; 1 << TABLE_BITS]),
+            input: vec![0; INPUT_BUFFER_SIZE].into_boxed_slice(),
+            input_pos: 0,
+            input_len: 0,
+            bit_buf: 0,
+            bit_count: 0,
+            consumed: 0,
+            checkpoints: vec![Checkpoint {
+                decoded: 0,
+                bit_position: 0,
+            }],
+            decoded: 0,
+            expected_len,
+            finished: false,
         };
         decoder.parse_header()?;
+        decoder.build_table();
         Ok(decoder)
     }
 
     /// Read and parse the data header. Creates the symbol table and the Huffman tree.
+    ///
+    /// All counts are validated before they are used, so a corrupt header results in an error.
     fn parse_header(&mut self) -> Result<()> {
-        let mut buffer = vec![0; 1];
+        let mut buffer = [0u8; 1];
         self.reader.read_exact(&mut buffer)?;
-        self.treelevels = buffer[0] as usize;
-        self.inodesin = vec![0; self.treelevels];
-        self.symbolsin = vec![0; self.treelevels];
-        self.tree = vec![Vec::new(); self.treelevels];
-        self.treelevels -= 1;
-        self.symbol_size = 1;
-
-        for i in 0..=self.treelevels {
-            self.reader.read_exact(&mut buffer)?;
-            self.symbolsin[i] = buffer[0];
-            self.symbol_size += self.symbolsin[i] as usize;
+        let levels = buffer[0] as usize;
+        if levels == 0 || levels > MAX_CODE_LENGTH {
+            return Err(Error::InvalidTreelevel);
         }
+        self.treelevels = levels - 1;
 
+        let mut symbolsin = vec![0; levels];
+        self.reader.read_exact(&mut symbolsin)?;
+        self.symbolsin = symbolsin.into_iter().map(usize::from).collect();
+        self.symbol_size = 1 + self.symbolsin.iter().sum::<usize>();
         if self.symbol_size > 256 {
             return Err(Error::BadSymbolTable);
         }
 
-        self.symbolsin[self.treelevels as usize] += 1;
-
-        for i in 0..=self.treelevels {
-            let mut symbol = Vec::new();
-            for _ in 0..self.symbolsin[i as usize] {
-                self.reader.read_exact(&mut buffer)?;
-                symbol.push(buffer[0]);
-            }
-            self.tree[i as usize] = symbol;
+        // The deepest level stores two symbols less than it holds. One of them is part of the
+        // symbol table, the other one is the end of stream code.
+        self.symbolsin[self.treelevels] += 1;
+        self.tree = Vec::with_capacity(levels);
+        for &count in self.symbolsin.iter() {
+            let mut symbols = vec![0; count];
+            self.reader.read_exact(&mut symbols)?;
+            self.tree.push(symbols);
         }
-
         self.symbolsin[self.treelevels] += 1;
 
-        self.fill_inodesin(0);
+        self.fill_inodesin()?;
         self.treelens = self.tree.iter().map(|l| l.len()).collect();
         Ok(())
     }
 
-    fn fill_inodesin(&mut self, level: usize) {
-        if level < self.treelevels {
-            self.fill_inodesin(level + 1);
-            self.inodesin[level] = (self.inodesin[level + 1] + self.symbolsin[level + 1]) / 2;
+    /// Compute the amount of internal nodes of every tree level, starting at the deepest one.
+    ///
+    /// Codes of a level are 8 bit wide, so a level can't hold more than 256 nodes.
+    fn fill_inodesin(&mut self) -> Result<()> {
+        self.inodesin = vec![0; self.treelevels + 1];
+        for level in (0..=self.treelevels).rev() {
+            let nodes = self.inodesin[level] + self.symbolsin[level];
+            if nodes > 256 {
+                return Err(Error::BadSymbolTable);
+            }
+            if level > 0 {
+                self.inodesin[level - 1] = nodes / 2;
+            } else if nodes > 2 {
+                // The root has two children only.
+                return Err(Error::BadSymbolTable);
+            }
+        }
+        Ok(())
+    }
+
+    /// Create the decoding table by walking the tree for every combination of [TABLE_BITS] bits.
+    fn build_table(&mut self) {
+        let table = (0..1u32 << TABLE_BITS)
+            .map(|bits| {
+                let mut code = 0u8;
+                let mut level = 0;
+                for consumed in 1..=TABLE_BITS {
+                    code = (code << 1) | ((bits >> (TABLE_BITS - consumed)) & 1) as u8;
+                    match self.step(code, level) {
+                        Step::Symbol(symbol) => return TableEntry::Symbol(symbol, consumed),
+                        Step::EndOfStream => return TableEntry::EndOfStream(consumed),
+                        Step::Next => level += 1,
+                        Step::Invalid(_) => return TableEntry::Invalid,
+                    }
+                }
+                TableEntry::Continue(code, level)
+            })
+            .collect::<Vec<_>>();
+        self.table.copy_from_slice(&table);
+    }
+
+    /// Resolve `code` on tree level `level`.
+    fn step(&self, code: u8, level: usize) -> Step {
+        let code = code as usize;
+        if code >= self.inodesin[level] {
+            let inlevelindex = code - self.inodesin[level];
+            if inlevelindex > self.symbolsin[level] {
+                Step::Invalid(Error::InvalidLevelIndex)
+            } else if self.treelens[level] <= inlevelindex {
+                Step::EndOfStream
+            } else {
+                Step::Symbol(self.tree[level][inlevelindex])
+            }
+        } else if level + 1 > self.treelevels {
+            Step::Invalid(Error::InvalidTreelevel)
         } else {
-            self.inodesin[level] = 0;
+            Step::Next
         }
     }
-}
 
-impl<R: Read> Read for HuffmanDecoder<R> {
-    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
-        let buf_size = buf.len();
-        let mut current_out = self.offset_buf.len();
-        let mut buffer = [0; 1];
-        let mut symbol;
-        let mut inlevelindex;
+    /// Move input bytes into the bit buffer. Reads the next input block if needed.
+    fn refill(&mut self) -> std::io::Result<()> {
+        if self.input_len - self.input_pos >= 8 {
+            // Load as many whole bytes as fit into the bit buffer at once.
+            let bytes = (64 - self.bit_count) / 8;
+            let chunk = u64::from_be_bytes(
+                self.input[self.input_pos..self.input_pos + 8]
+                    .try_into()
+                    .expect("slice has 8 bytes"),
+            );
+            self.bit_buf =
+                self.bit_buf.checked_shl(bytes * 8).unwrap_or(0) | (chunk >> (64 - bytes * 8));
+            self.input_pos += bytes as usize;
+            self.bit_count += bytes * 8;
+            return Ok(());
+        }
+        while self.bit_count <= 56 {
+            if self.input_pos == self.input_len {
+                self.input_pos = 0;
+                self.input_len = loop {
+                    match self.reader.read(&mut self.input) {
+                        Err(e) if e.kind() == ErrorKind::Interrupted => {}
+                        result => break result?,
+                    }
+                };
+                self.consumed += self.input_len as u64;
+                if self.input_len == 0 {
+                    return Ok(());
+                }
+            }
+            self.bit_buf = (self.bit_buf << 8) | u64::from(self.input[self.input_pos]);
+            self.input_pos += 1;
+            self.bit_count += 8;
+        }
+        Ok(())
+    }
 
-        // Read in extracted bytes from previous call
-        let offset_read_len = min(buf_size, self.offset_buf.len());
-        for i in 0..offset_read_len {
-            buf[i] = match self.offset_buf.pop_front() {
-                Some(value) => value,
-                None => break,
+    /// Decode bit by bit, starting at `code` on tree level `level`.
+    fn walk(&mut self, mut code: u8, mut level: usize) -> std::io::Result<Decoded> {
+        loop {
+            if self.bit_count == 0 {
+                self.refill()?;
+                if self.bit_count == 0 {
+                    return Ok(Decoded::EndOfInput);
+                }
+            }
+            self.bit_count -= 1;
+            code = (code << 1) | ((self.bit_buf >> self.bit_count) & 1) as u8;
+            match self.step(code, level) {
+                Step::Symbol(symbol) => return Ok(Decoded::Symbol(symbol)),
+                Step::EndOfStream => return Ok(Decoded::EndOfStream),
+                Step::Next => level += 1,
+                Step::Invalid(error) => return Err(std::io::Error::other(error)),
             }
         }
-        if offset_read_len == buf_size {
-            return Ok(offset_read_len);
+    }
+
+    /// Decode the next code of the input.
+    fn next_symbol(&mut self) -> std::io::Result<Decoded> {
+        if self.bit_count < TABLE_BITS {
+            self.refill()?;
+            if self.bit_count < TABLE_BITS {
+                return self.walk(0, 0);
+            }
         }
+        let index = (self.bit_buf >> (self.bit_count - TABLE_BITS)) as usize & TABLE_MASK;
+        match self.table[index] {
+            TableEntry::Symbol(symbol, length) => {
+                self.bit_count -= length;
+                Ok(Decoded::Symbol(symbol))
+            }
+            TableEntry::EndOfStream(length) => {
+                self.bit_count -= length;
+                Ok(Decoded::EndOfStream)
+            }
+            TableEntry::Continue(code, level) => {
+                self.bit_count -= TABLE_BITS;
+                self.walk(code, level)
+            }
+            TableEntry::Invalid => self.walk(0, 0),
+        }
+    }
 
-        // Read new bytes from input
-        while current_out < buf_size {
-            match self.reader.read_exact(&mut buffer) {
-                Err(e) if e.kind() == ErrorKind::UnexpectedEof => return Ok(current_out),
-                _ => (),
-            };
+    /// Returns the position of the next code.
+    fn checkpoint(&self) -> Checkpoint {
+        let unused_bytes = (self.input_len - self.input_pos) as u64;
+        Checkpoint {
+            decoded: self.decoded,
+            bit_position: (self.consumed - unused_bytes) * 8 - u64::from(self.bit_count),
+        }
+    }
 
-            for i in (0..=7).rev() {
-                self.code = (self.code << 1) | ((buffer[0] >> i) & 1);
-                if self.code >= self.inodesin[self.level] {
-                    inlevelindex = (self.code - self.inodesin[self.level]) as usize;
-                    if inlevelindex > self.symbolsin[self.level] as usize {
-                        return Err(std::io::Error::other(Error::InvalidLevelIndex));
-                    }
-                    if self.treelens[self.level] <= inlevelindex {
-                        // Hopefully the end of the file
-                        return Ok(current_out);
+    /// Returns the amount of decoded bytes at which the next checkpoint will be recorded.
+    fn next_checkpoint(&self) -> u64 {
+        self.checkpoints.len() as u64 * CHECKPOINT_INTERVAL
+    }
+
+    /// Decode and discard up to `len` bytes. Returns the amount of bytes skipped.
+    fn skip(&mut self, mut len: u64) -> std::io::Result<u64> {
+        let mut buf = [0u8; 8 * 1024];
+        let mut skipped = 0;
+        while len > 0 {
+            let chunk = len.min(buf.len() as u64) as usize;
+            let read = self.read(&mut buf[..chunk])?;
+            if read == 0 {
+                break;
+            }
+            skipped += read as u64;
+            len -= read as u64;
+        }
+        Ok(skipped)
+    }
+
+    /// Count the symbols following the expected end of the stream and return the resulting error.
+    fn overrun_error(&mut self, expected: u64) -> std::io::Error {
+        let mut actual = self.decoded + 1;
+        while let Ok(Decoded::Symbol(_)) = self.next_symbol() {
+            actual += 1;
+        }
+        std::io::Error::other(Error::DecodedSizeMismatch { expected, actual })
+    }
+}
+
+/// Result of resolving a code on a single tree level.
+enum Step {
+    Symbol(u8),
+    EndOfStream,
+    /// The code is an internal node, continue on the next level.
+    Next,
+    Invalid(Error),
+}
+
+impl<R: Read> Read for HuffmanDecoder<R> {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        let mut current_out = 0;
+        while current_out < buf.len() && !self.finished {
+            if self.decoded == self.next_checkpoint() {
+                let checkpoint = self.checkpoint();
+                self.checkpoints.push(checkpoint);
+            }
+
+            // Fast path: decode codes resolved by the table without further checks.
+            let remaining = match self.expected_len {
+                Some(expected) => expected
+                    .saturating_sub(self.decoded)
+                    .min(self.next_checkpoint() - self.decoded),
+                None => self.next_checkpoint() - self.decoded,
+            };
+            let limit = buf
+                .len()
+                .min(current_out.saturating_add(remaining.try_into().unwrap_or(usize::MAX)));
+            let start = current_out;
+            while current_out < limit {
+                if self.bit_count < TABLE_BITS {
+                    self.refill()?;
+                    if self.bit_count < TABLE_BITS {
+                        break;
                     }
-                    symbol = self.tree[self.level][inlevelindex];
-                    if current_out >= buf_size {
-                        self.offset_buf.push_back(symbol);
-                    } else {
-                        buf[current_out] = symbol;
+                }
+                // Decode all codes available in the bit buffer.
+                let (bit_buf, mut bit_count) = (self.bit_buf, self.bit_count);
+                while bit_count >= TABLE_BITS && current_out < limit {
+                    let index = (bit_buf >> (bit_count - TABLE_BITS)) as usize & TABLE_MASK;
+                    let TableEntry::Symbol(symbol, length) = self.table[index] else {
+                        break;
+                    };
+                    bit_count -= length;
+                    buf[current_out] = symbol;
+                    current_out += 1;
+                }
+                let stalled = bit_count >= TABLE_BITS && current_out < limit;
+                self.bit_count = bit_count;
+                if stalled {
+                    break;
+                }
+            }
+            self.decoded += (current_out - start) as u64;
+            if current_out == buf.len() || self.decoded == self.next_checkpoint() {
+                continue;
+            }
+
+            match self.next_symbol()? {
+                Decoded::Symbol(symbol) => {
+                    if let Some(expected) = self.expected_len
+                        && self.decoded >= expected
+                    {
+                        self.finished = true;
+                        return Err(self.overrun_error(expected));
                     }
+                    buf[current_out] = symbol;
                     current_out += 1;
-                    self.code = 0;
-                    self.level = 0;
-                } else {
-                    self.level += 1;
-                    if self.level > self.treelevels {
-                        return Err(std::io::Error::other(Error::InvalidTreelevel));
+                    self.decoded += 1;
+                }
+                Decoded::EndOfStream | Decoded::EndOfInput => {
+                    self.finished = true;
+                    if let Some(expected) = self.expected_len
+                        && self.decoded != expected
+                    {
+                        return Err(std::io::Error::other(Error::DecodedSizeMismatch {
+                            expected,
+                            actual: self.decoded,
+                        }));
                     }
                 }
             }
         }
-        Ok(min(current_out, buf_size))
+        Ok(current_out)
+    }
+}
+
+impl<R: Read + Seek> Seek for HuffmanDecoder<R> {
+    /// Seek to an offset in the decoded data.
+    ///
+    /// Seeking to [SeekFrom::End] needs the decoded length. If the decoder has no expected length,
+    /// the whole stream is decoded first. Seeking beyond the end of the decoded data positions the
+    /// decoder at the end.
+    fn seek(&mut self, pos: SeekFrom) -> std::io::Result<u64> {
+        let target = match pos {
+            SeekFrom::Start(offset) => Some(offset),
+            SeekFrom::Current(offset) => self.decoded.checked_add_signed(offset),
+            SeekFrom::End(offset) => {
+                let len = match self.expected_len {
+                    Some(expected_len) => expected_len,
+                    None => self.decoded + self.skip(u64::MAX)?,
+                };
+                len.checked_add_signed(offset)
+            }
+        }
+        .ok_or_else(|| {
+            std::io::Error::new(
+                ErrorKind::InvalidInput,
+                "invalid seek to a negative or overflowing position",
+            )
+        })?;
+
+        // Resume at the closest checkpoint if the target is behind it or it is closer than the
+        // current position.
+        let index = self
+            .checkpoints
+            .partition_point(|checkpoint| checkpoint.decoded <= target)
+            - 1;
+        let checkpoint = self.checkpoints[index];
+        if target < self.decoded || checkpoint.decoded > self.decoded {
+            let byte_position = checkpoint.bit_position / 8;
+            self.reader.seek(SeekFrom::Current(
+                byte_position as i64 - self.consumed as i64,
+            ))?;
+            self.consumed = byte_position;
+            self.input_pos = 0;
+            self.input_len = 0;
+            self.bit_buf = 0;
+            self.bit_count = 0;
+            self.decoded = checkpoint.decoded;
+            self.finished = false;
+            let bit_offset = (checkpoint.bit_position % 8) as u32;
+            if bit_offset > 0 {
+                self.refill()?;
+                self.bit_count = self.bit_count.saturating_sub(bit_offset);
+            }
+        }
+
+        self.skip(target - self.decoded)?;
+        Ok(self.decoded)
+    }
+}
+
+/// An encoder producing Huffman encoded BFF file contents readable by [HuffmanDecoder].
+///
+/// Huffman coding needs the symbol frequencies of the whole input, so all written data is
+/// buffered until [HuffmanEncoder::finish] is called.
+pub struct HuffmanEncoder<W: Write> {
+    /// Target writer receiving the compressed data
+    writer: W,
+    /// Uncompressed data written so far
+    data: Vec<u8>,
+}
+
+impl<W: Write> HuffmanEncoder<W> {
+    /// Create a new instance of `HuffmanEncoder` by providing a writer.
+    pub fn new(writer: W) -> Self {
+        Self {
+            writer,
+            data: vec![],
+        }
+    }
+
+    /// Encode all data written so far and return the underlying writer.
+    ///
+    /// Writes the header (tree level count, symbol count per level, symbol table), the encoded
+    /// symbols and the end of stream code. The last byte is padded with zero bits.
+    pub fn finish(mut self) -> Result<W> {
+        let mut frequencies = [0u64; EOF_SYMBOL + 1];
+        self.data
+            .iter()
+            .for_each(|&byte| frequencies[byte as usize] += 1);
+        let lengths = code_lengths(&frequencies);
+        let treelevels = lengths.iter().copied().max().unwrap_or_default();
+
+        // Symbols of every level in table order. The end of stream code is the last one of the
+        // deepest level and is not stored in the table.
+        let mut levels = vec![Vec::new(); treelevels];
+        (0..EOF_SYMBOL)
+            .filter(|&symbol| lengths[symbol] > 0)
+            .for_each(|symbol| levels[lengths[symbol] - 1].push(symbol as u8));
+        let mut counts: Vec<usize> = levels.iter().map(Vec::len).collect();
+        counts[treelevels - 1] += 1;
+
+        // Internal nodes occupy the lowest codes of every level, followed by the leaves.
+        let mut inodes = vec![0usize; treelevels];
+        for level in (0..treelevels - 1).rev() {
+            inodes[level] = (inodes[level + 1] + counts[level + 1]) / 2;
+        }
+        let mut codes = [(0u32, 0usize); EOF_SYMBOL + 1];
+        for (level, symbols) in levels.iter().enumerate() {
+            for (index, &symbol) in symbols.iter().enumerate() {
+                codes[symbol as usize] = ((inodes[level] + index) as u32, level + 1);
+            }
+        }
+        codes[EOF_SYMBOL] = (
+            (inodes[treelevels - 1] + levels[treelevels - 1].len()) as u32,
+            treelevels,
+        );
+
+        let mut header = vec![treelevels as u8];
+        header.extend(counts.iter().map(|&count| count as u8));
+        header[treelevels] -= 2;
+        levels.iter().for_each(|symbols| header.extend(symbols));
+        self.writer.write_all(&header)?;
+
+        let mut bits = BitWriter::new(&mut self.writer);
+        for &byte in self.data.iter() {
+            let (code, length) = codes[byte as usize];
+            bits.write(code, length)?;
+        }
+        let (code, length) = codes[EOF_SYMBOL];
+        bits.write(code, length)?;
+        bits.finish()?;
+
+        self.writer.flush()?;
+        Ok(self.writer)
+    }
+}
+
+impl<W: Write> Write for HuffmanEncoder<W> {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        self.data.extend_from_slice(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        Ok(())
+    }
+}
+
+/// Writes codes most significant bit first.
+struct BitWriter<W> {
+    writer: W,
+    buffer: u64,
+    count: usize,
+}
+
+impl<W: Write> BitWriter<W> {
+    fn new(writer: W) -> Self {
+        Self {
+            writer,
+            buffer: 0,
+            count: 0,
+        }
+    }
+
+    fn write(&mut self, code: u32, length: usize) -> std::io::Result<()> {
+        self.buffer = (self.buffer << length) | u64::from(code);
+        self.count += length;
+        while self.count >= 8 {
+            self.count -= 8;
+            self.writer
+                .write_all(&[(self.buffer >> self.count) as u8])?;
+        }
+        Ok(())
+    }
+
+    fn finish(mut self) -> std::io::Result<()> {
+        if self.count > 0 {
+            self.write(0, 8 - self.count)?;
+        }
+        Ok(())
     }
 }
 
+/// Compute the code length of every symbol including the end of stream marker. Unused symbols have length 0.
+///
+/// The resulting tree fits into the table format of [HuffmanDecoder]: codes are at most
+/// [MAX_CODE_LENGTH] bits long, no level holds more than 255 nodes and the end of stream
+/// marker is located on the deepest level.
+fn code_lengths(frequencies: &[u64; EOF_SYMBOL + 1]) -> [usize; EOF_SYMBOL + 1] {
+    let mut weights = *frequencies;
+    // The symbol table needs at least one symbol besides the end of stream marker.
+    if weights[..EOF_SYMBOL].iter().all(|&weight| weight == 0) {
+        weights[0] = 1;
+    }
+    weights[EOF_SYMBOL] = 1;
+    let used: Vec<bool> = weights.iter().map(|&weight| weight > 0).collect();
+
+    loop {
+        let mut lengths = huffman_lengths(&weights, &used);
+        let max_length = lengths.iter().copied().max().unwrap_or_default();
+
+        if max_length > MAX_CODE_LENGTH {
+            // Flatten the distribution until the tree is shallow enough.
+            weights
+                .iter_mut()
+                .zip(used.iter())
+                .filter(|(_, used)| **used)
+                .for_each(|(weight, _)| *weight = (*weight >> 1).max(1));
+            continue;
+        }
+
+        if !fits_decoder_table(&lengths, max_length) {
+            // Only nearly uniform distributions of almost all byte values exceed 255 nodes per
+            // level. Giving the two most frequent symbols short codes keeps every level small.
+            let total: u64 = weights.iter().sum();
+            let mut order: Vec<usize> = (0..EOF_SYMBOL).filter(|&symbol| used[symbol]).collect();
+            order.sort_by_key(|&symbol| Reverse(weights[symbol]));
+            weights[order[0]] = total * 4;
+            weights[order[1]] = total * 2;
+            continue;
+        }
+
+        // Move the end of stream marker to the deepest level.
+        if lengths[EOF_SYMBOL] < max_length {
+            let deepest = (0..EOF_SYMBOL)
+                .find(|&symbol| lengths[symbol] == max_length)
+                .expect("deepest level holds a symbol");
+            lengths.swap(deepest, EOF_SYMBOL);
+        }
+        return lengths;
+    }
+}
+
+/// Build a Huffman tree of all used symbols and return the depth of every leaf.
+fn huffman_lengths(weights: &[u64; EOF_SYMBOL + 1], used: &[bool]) -> [usize; EOF_SYMBOL + 1] {
+    let mut parents: Vec<usize> = vec![usize::MAX; EOF_SYMBOL + 1];
+    let mut heap: BinaryHeap<Reverse<(u64, usize)>> = (0..=EOF_SYMBOL)
+        .filter(|&symbol| used[symbol])
+        .map(|symbol| Reverse((weights[symbol], symbol)))
+        .collect();
+
+    while heap.len() > 1 {
+        let Reverse((weight_a, node_a)) = heap.pop().unwrap();
+        let Reverse((weight_b, node_b)) = heap.pop().unwrap();
+        let node = parents.len();
+        parents.push(usize::MAX);
+        parents[node_a] = node;
+        parents[node_b] = node;
+        heap.push(Reverse((weight_a + weight_b, node)));
+    }
+
+    let mut lengths = [0usize; EOF_SYMBOL + 1];
+    for symbol in (0..=EOF_SYMBOL).filter(|&symbol| used[symbol]) {
+        let mut node = symbol;
+        while parents[node] != usize::MAX {
+            node = parents[node];
+            lengths[symbol] += 1;
+        }
+    }
+    lengths
+}
+
+/// Checks that every level of the tree stays within the 8-bit counters of [HuffmanDecoder].
+fn fits_decoder_table(lengths: &[usize; EOF_SYMBOL + 1], max_length: usize) -> bool {
+    let mut leaves = vec![0usize; max_length + 1];
+    lengths
+        .iter()
+        .filter(|&&length| length > 0)
+        .for_each(|&length| leaves[length] += 1);
+
+    let mut inodes = 0;
+    for level in (1..=max_length).rev() {
+        if inodes + leaves[level] > 255 {
+            return false;
+        }
+        inodes = (inodes + leaves[level]) / 2;
+    }
+    true
+}
+
 #[cfg(test)]
 mod tests {
-    use std::{fs::File, io::{Read, Result}, path::{Path, PathBuf}};
+    use std::{
+        fs::File,
+        io::{Read, Result, Seek, SeekFrom, Write},
+        path::{Path, PathBuf},
+    };
 
-    use super::HuffmanDecoder;
+    use super::{HuffmanDecoder, HuffmanEncoder};
+    use crate::Error;
 
     fn get_resources_path() -> PathBuf {
         let mut dir = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
@@ -168,7 +741,7 @@ mod tests {
 
     fn open_decoder<P: AsRef<Path>>(filename: P) -> Result<impl Read> {
         let file = File::open(get_resources_path().join(filename))?;
-        let decoder = HuffmanDecoder::new(file).map_err(|e| std::io::Error::other(e))?;
+        let decoder = HuffmanDecoder::new(file).map_err(std::io::Error::other)?;
         Ok(decoder)
     }
 
@@ -189,10 +762,211 @@ mod tests {
         let mut decoded_file = File::open(get_resources_path().join("huffman_decoded.bin"))?;
         let mut decoder = open_decoder("huffman_encoded.bin")?;
         let mut result = [0u8; 16];
-        decoder.read(&mut result)?;
+        let len = decoder.read(&mut result)?;
         let mut expected = [0u8; 16];
-        decoded_file.read(&mut expected)?;
+        decoded_file.read_exact(&mut expected)?;
+        assert_eq!(len, 16);
         assert_eq!(result, expected);
         Ok(())
     }
+
+    fn round_trip(data: &[u8]) -> Vec<u8> {
+        let mut encoder = HuffmanEncoder::new(vec![]);
+        encoder.write_all(data).unwrap();
+        let encoded = encoder.finish().unwrap();
+        let mut decoder = HuffmanDecoder::new(encoded.as_slice()).unwrap();
+        let mut decoded = vec![];
+        decoder.read_to_end(&mut decoded).unwrap();
+        decoded
+    }
+
+    #[test]
+    fn encode_decoded_file() -> Result<()> {
+        let mut expected = vec![];
+        File::open(get_resources_path().join("huffman_decoded.bin"))?.read_to_end(&mut expected)?;
+
+        let mut encoder = HuffmanEncoder::new(vec![]);
+        encoder.write_all(&expected)?;
+        let encoded = encoder.finish().map_err(std::io::Error::other)?;
+        assert!(encoded.len() < expected.len());
+
+        let mut result = vec![];
+        HuffmanDecoder::new(encoded.as_slice())
+            .map_err(std::io::Error::other)?
+            .read_to_end(&mut result)?;
+        assert_eq!(result, expected);
+        Ok(())
+    }
+
+    fn encode(data: &[u8]) -> Vec<u8> {
+        let mut encoder = HuffmanEncoder::new(vec![]);
+        encoder.write_all(data).unwrap();
+        encoder.finish().unwrap()
+    }
+
+    fn decode_with_expected_len(encoded: &[u8], expected_len: u64) -> crate::Result<Vec<u8>> {
+        let mut decoder = HuffmanDecoder::with_expected_len(encoded, expected_len)?;
+        let mut decoded = vec![];
+        decoder.read_to_end(&mut decoded)?;
+        Ok(decoded)
+    }
+
+    #[test]
+    fn decode_expected_len_matches() {
+        let encoded = encode(b"abracadabra");
+
+        assert_eq!(
+            decode_with_expected_len(&encoded, 11).unwrap(),
+            b"abracadabra"
+        );
+    }
+
+    #[test]
+    fn decode_expected_len_underrun() {
+        let encoded = encode(b"abracadabra");
+
+        assert!(matches!(
+            decode_with_expected_len(&encoded, 12),
+            Err(Error::DecodedSizeMismatch {
+                expected: 12,
+                actual: 11
+            })
+        ));
+    }
+
+    #[test]
+    fn decode_expected_len_overrun() {
+        let encoded = encode(b"abracadabra");
+
+        assert!(matches!(
+            decode_with_expected_len(&encoded, 4),
+            Err(Error::DecodedSizeMismatch {
+                expected: 4,
+                actual: 11
+            })
+        ));
+    }
+
+    #[test]
+    fn decode_expected_len_truncated_input() {
+        let data = b"some longer text which is cut off in the middle".repeat(4);
+        let encoded = encode(&data);
+
+        assert!(matches!(
+            decode_with_expected_len(&encoded[..encoded.len() / 2], data.len() as u64),
+            Err(Error::DecodedSizeMismatch { actual, .. }) if actual < data.len() as u64
+        ));
+    }
+
+    #[test]
+    fn seek_uses_checkpoints() -> Result<()> {
+        // Several checkpoint intervals of not too regular data.
+        let data: Vec<u8> = (0..700_000u32)
+            .map(|i| (i.wrapping_mul(2_654_435_761) >> 27) as u8)
+            .collect();
+        let encoded = encode(&data);
+        let mut decoder =
+            HuffmanDecoder::with_expected_len(std::io::Cursor::new(encoded), data.len() as u64)
+                .map_err(std::io::Error::other)?;
+        let mut buf = [0u8; 100];
+
+        for &offset in &[600_000u64, 10, 300_001, 699_950, 262_144, 0] {
+            assert_eq!(decoder.seek(SeekFrom::Start(offset))?, offset);
+            let len = decoder.read(&mut buf)?;
+            let offset = offset as usize;
+            assert_eq!(&buf[..len], &data[offset..(offset + 100).min(data.len())]);
+        }
+
+        assert_eq!(decoder.seek(SeekFrom::End(-3))?, data.len() as u64 - 3);
+        let mut tail = vec![];
+        decoder.read_to_end(&mut tail)?;
+        assert_eq!(tail, &data[data.len() - 3..]);
+
+        decoder.seek(SeekFrom::Current(-10))?;
+        assert_eq!(decoder.stream_position()?, data.len() as u64 - 10);
+        Ok(())
+    }
+
+    #[test]
+    fn seek_without_expected_len() -> Result<()> {
+        let data = b"seeking in a stream of unknown length".repeat(100);
+        let mut decoder = HuffmanDecoder::new(std::io::Cursor::new(encode(&data)))
+            .map_err(std::io::Error::other)?;
+
+        assert_eq!(decoder.seek(SeekFrom::End(-6))?, data.len() as u64 - 6);
+        let mut tail = String::new();
+        decoder.read_to_string(&mut tail)?;
+        assert_eq!(tail, "length");
+
+        assert!(
+            decoder
+                .seek(SeekFrom::Current(-(data.len() as i64) - 1))
+                .is_err()
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn rejects_zero_tree_levels() {
+        assert!(matches!(
+            HuffmanDecoder::new([0u8].as_slice()),
+            Err(Error::InvalidTreelevel)
+        ));
+    }
+
+    #[test]
+    fn rejects_overfull_deepest_level() {
+        let mut header = vec![1u8, 255];
+        header.extend(0..=255);
+
+        assert!(matches!(
+            HuffmanDecoder::new(header.as_slice()),
+            Err(Error::BadSymbolTable)
+        ));
+    }
+
+    #[test]
+    fn rejects_overfull_root_level() {
+        let mut header = vec![2u8, 1, 100];
+        header.extend(0..102);
+
+        assert!(matches!(
+            HuffmanDecoder::new(header.as_slice()),
+            Err(Error::BadSymbolTable)
+        ));
+    }
+
+    #[test]
+    fn rejects_truncated_symbol_table() {
+        assert!(matches!(
+            HuffmanDecoder::new([2u8, 1, 3, b'a', b'b'].as_slice()),
+            Err(Error::IoError(_))
+        ));
+    }
+
+    #[test]
+    fn encode_small_inputs() {
+        assert_eq!(round_trip(b""), b"");
+        assert_eq!(round_trip(b"a"), b"a");
+        assert_eq!(round_trip(b"aaaaaaaa"), b"aaaaaaaa");
+        assert_eq!(round_trip(b"abracadabra"), b"abracadabra");
+    }
+
+    #[test]
+    fn encode_uniform_byte_distribution() {
+        let data: Vec<u8> = (0..16 * 256).map(|i| i as u8).collect();
+        assert_eq!(round_trip(&data), data);
+    }
+
+    #[test]
+    fn encode_skewed_distribution() {
+        // Fibonacci-like frequencies produce codes longer than the format allows.
+        let mut data = vec![];
+        let (mut a, mut b) = (1usize, 1usize);
+        for symbol in 0..30u8 {
+            data.extend(std::iter::repeat_n(symbol, a));
+            (a, b) = (b, a + b);
+        }
+        assert_eq!(round_trip(&data), data);
+    }
 }
@@ -0,0 +1,116 @@
+//! Summary of the metadata of an archive
+//!
+//! [ArchiveInfo] combines the identity fields of the [FileHeader] with statistics of the records,
+//! so archives can be cataloged without extracting them.
+
+use chrono::{DateTime, Utc};
+
+use crate::{
+    archive::{EntryKind, Record},
+    bff::FileHeader,
+};
+
+/// Metadata and statistics of an archive.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ArchiveInfo {
+    /// Backup mode string, typically `by name`
+    pub disk_name: String,
+    /// Backup mode string, typically `by name`
+    pub filesystem_name: String,
+    /// Name of the user who created the archive
+    pub username: String,
+    /// Creation time of the archive
+    pub current_date: DateTime<Utc>,
+    /// Start time of the backup run
+    pub starting_date: DateTime<Utc>,
+    /// Amount of all records including separate ACL records
+    pub records: usize,
+    /// Amount of regular files
+    pub files: usize,
+    /// Amount of directories
+    pub directories: usize,
+    /// Amount of symbolic links
+    pub symlinks: usize,
+    /// Amount of other records, like devices, FIFOs and separate ACL records
+    pub others: usize,
+    /// Total size of all regular files
+    pub total_size: u64,
+    /// Total size of the payloads of all regular files as stored in the archive
+    pub stored_size: u64,
+}
+
+impl ArchiveInfo {
+    /// Summarize the archive with the `header` and the `records`.
+    pub fn new(header: &FileHeader, records: &[Record]) -> Self {
+        let mut info = Self {
+            disk_name: header.disk_name(),
+            filesystem_name: header.filesystem_name(),
+            username: header.username(),
+            current_date: header.current_date(),
+            starting_date: header.starting_date(),
+            records: records.len(),
+            files: 0,
+            directories: 0,
+            symlinks: 0,
+            others: 0,
+            total_size: 0,
+            stored_size: 0,
+        };
+        for record in records {
+            match record.kind() {
+                EntryKind::File => {
+                    info.files += 1;
+                    // Sizes of corrupt records may add up beyond u64.
+                    info.total_size = info.total_size.saturating_add(record.size());
+                    info.stored_size = info.stored_size.saturating_add(record.compressed_size());
+                }
+                EntryKind::Directory => info.directories += 1,
+                EntryKind::Symlink => info.symlinks += 1,
+                _ => info.others += 1,
+            }
+        }
+        info
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+
+    use crate::{archive::Archive, writer::ArchiveWriter};
+
+    #[test]
+    fn summarizes_sample_archive() {
+        let archive =
+            Archive::new(Cursor::new(include_bytes!("../../resources/test/test.bff"))).unwrap();
+
+        let info = archive.info();
+
+        assert_eq!(info.disk_name, "by name");
+        assert_eq!(info.filesystem_name, "by name");
+        assert_eq!(info.username, "root");
+        assert_eq!(info.current_date.to_rfc3339(), "2024-05-27T10:04:34+00:00");
+        assert_eq!(info.records, 4);
+        assert_eq!(info.files, 2);
+        assert_eq!(info.directories, 2);
+        assert_eq!(info.symlinks, 0);
+    }
+
+    #[test]
+    fn sums_sizes_of_files() {
+        let mut writer = ArchiveWriter::new(Vec::new()).unwrap();
+        writer
+            .append_file("./plain.txt", &Default::default(), b"plain")
+            .unwrap();
+        writer
+            .append_file_compressed("./packed.txt", &Default::default(), &b"data ".repeat(20))
+            .unwrap();
+        let archive = Archive::new(Cursor::new(writer.finish().unwrap())).unwrap();
+
+        let info = archive.info();
+
+        assert_eq!(info.files, 2);
+        assert_eq!(info.total_size, 105);
+        assert!(info.stored_size < info.total_size);
+    }
+}
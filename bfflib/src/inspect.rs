@@ -0,0 +1,418 @@
+//! Structural dump of the records of an archive for format research
+//!
+//! [inspect] decodes every field of the record headers and ACL trailers without validating them,
+//! so records rejected by the parser can be examined too. [RecordDump::error] tells why a record
+//! was rejected. Behind a rejected record the next plausible record header is searched the same
+//! way as in recovery mode.
+
+use std::io::{Read, Seek, SeekFrom};
+
+use chrono::DateTime;
+use file_mode::Mode;
+
+use crate::{
+    Result,
+    acl::{RecordAcl, S_IXACL, TRAILER_INLINE_ACL_BYTES},
+    archive::{find_record_header, read_next_record},
+    bff::{
+        FileHeader, HEADER_MAGICS, HUFFMAN_MAGIC, OFFSET_FORMAT_MARKER, RECORD_FORMAT_MARKER,
+        RecordHeader, SHORT_HEADER_LEN, compute_file_header_checksum,
+    },
+    stream::read_up_to,
+};
+
+/// Names are read up to this length. Longer names are cut off.
+const MAX_NAME_LEN: usize = 4096;
+/// ACL payloads are read up to this length. Longer payloads are cut off.
+const MAX_ACL_LEN: u64 = 64 * 1024;
+
+/// Name, position and size of the fields of a [RecordHeader].
+const HEADER_FIELDS: [(&str, usize, usize); 18] = [
+    ("unk00", 0, 1),
+    ("unk01", 1, 1),
+    ("magic", 2, 2),
+    ("unk04", 4, 4),
+    ("unk08", 8, 4),
+    ("mode", 12, 4),
+    ("uid", 16, 4),
+    ("gid", 20, 4),
+    ("size", 24, 4),
+    ("atime", 28, 4),
+    ("mtime", 32, 4),
+    ("time24", 36, 4),
+    ("unk28", 40, 4),
+    ("unk2_c", 44, 4),
+    ("unk30", 48, 4),
+    ("unk34", 52, 4),
+    ("compressed_size", 56, 4),
+    ("unk3_c", 60, 4),
+];
+
+/// Name, position and size of the fields of a [RecordAcl].
+const ACL_FIELDS: [(&str, usize, usize); 5] = [
+    ("num_entries", 0, 4),
+    ("version", 4, 4),
+    ("acl_len", 8, 4),
+    ("acl_mode", 12, 4),
+    ("acl_payload_bytes", 16, TRAILER_INLINE_ACL_BYTES),
+];
+
+/// A field of a record decoded without validation.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Field {
+    /// Name of the field in [RecordHeader] or [RecordAcl]
+    pub name: &'static str,
+    /// Position of the field in the archive
+    pub offset: u64,
+    /// Bytes of the field as stored in the archive
+    pub raw: Vec<u8>,
+    /// Interpretation of the value
+    pub decoded: String,
+}
+
+impl Field {
+    /// Returns the raw value in hex. Fields up to 8 bytes are read as little-endian number.
+    pub fn hex(&self) -> String {
+        if self.raw.len() > 8 {
+            return hex_bytes(&self.raw);
+        }
+        let value = self
+            .raw
+            .iter()
+            .rev()
+            .fold(0u64, |value, &byte| (value << 8) | u64::from(byte));
+        format!("{:#0width$x}", value, width = self.raw.len() * 2 + 2)
+    }
+
+    /// Returns the raw value as little-endian number. Longer fields return 0.
+    fn value(&self) -> u32 {
+        match self.raw.len() {
+            1..=4 => self
+                .raw
+                .iter()
+                .rev()
+                .fold(0, |value, &byte| (value << 8) | u32::from(byte)),
+            _ => 0,
+        }
+    }
+}
+
+/// Format bytes as hex digits without separators.
+pub fn hex_bytes(bytes: &[u8]) -> String {
+    bytes.iter().map(|byte| format!("{byte:02x}")).collect()
+}
+
+/// The structure of a record as stored in the archive.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct RecordDump {
+    /// Position of the record in the archive
+    pub offset: u64,
+    /// Fields of the [RecordHeader]. Offset records consist of the common header only. Fields cut
+    /// off by the end of the data are missing.
+    pub header: Vec<Field>,
+    /// Checksum computed over header and name. `None` if the length field is invalid.
+    pub computed_checksum: Option<u16>,
+    /// Name of the record up to the terminating NUL
+    pub name: Option<String>,
+    /// Bytes behind the terminating NUL of the name up to the next 8-byte boundary
+    pub name_padding: Vec<u8>,
+    /// Target of a symbolic link
+    pub symlink: Option<String>,
+    /// Fields of the [RecordAcl]
+    pub acl: Vec<Field>,
+    /// ACL payload including the bytes stored inside the [RecordAcl]
+    pub acl_payload: Vec<u8>,
+    /// Position of the payload
+    pub payload_start: Option<u64>,
+    /// Position behind the payload without padding
+    pub payload_end: Option<u64>,
+    /// Why the parser rejected the record. `None` if it was accepted.
+    pub error: Option<String>,
+}
+
+impl RecordDump {
+    /// Returns the header field of the given name.
+    pub fn field(&self, name: &str) -> Option<&Field> {
+        self.header.iter().find(|field| field.name == name)
+    }
+}
+
+/// Dump the structure of all records of an archive.
+///
+/// The file header isn't validated, the records are expected behind it.
+pub fn inspect<R: Read + Seek>(reader: &mut R) -> Result<Vec<RecordDump>> {
+    let mut dumps = vec![];
+    let mut position = std::mem::size_of::<FileHeader>() as u64;
+    let mut after_offset_record = false;
+    loop {
+        let Some(mut dump) = dump_record(reader, position)? else {
+            break;
+        };
+        reader.seek(SeekFrom::Start(position))?;
+        let next = match read_next_record(reader) {
+            Ok(Some(_)) => {
+                after_offset_record = false;
+                Some(reader.stream_position()?)
+            }
+            // The length of offset records is stored in 8-byte units.
+            Ok(None) => {
+                after_offset_record = true;
+                let units = dump.field("unk00").map_or(1, Field::value).max(1);
+                Some(position + u64::from(units) * 8)
+            }
+            // Behind an offset record the data is padding, unless a valid record follows.
+            Err(_) if after_offset_record => break,
+            Err(error) => {
+                dump.error = Some(error.to_string());
+                find_record_header(reader, position + 8)?
+            }
+        };
+        dumps.push(dump);
+        match next {
+            Some(next) => position = next,
+            None => break,
+        }
+    }
+    Ok(dumps)
+}
+
+/// Decode the record at `position` without validating it. Returns `None` at the end of the data.
+fn dump_record<R: Read + Seek>(reader: &mut R, position: u64) -> Result<Option<RecordDump>> {
+    let header_len = std::mem::size_of::<RecordHeader>();
+    reader.seek(SeekFrom::Start(position))?;
+    let mut header = vec![0u8; header_len];
+    let len = read_up_to(reader, &mut header)?;
+    if len == 0 {
+        return Ok(None);
+    }
+    header.truncate(len);
+    let mut dump = RecordDump {
+        offset: position,
+        ..Default::default()
+    };
+
+    let offset_record = header.get(1) == Some(&OFFSET_FORMAT_MARKER);
+    if offset_record {
+        header.truncate(SHORT_HEADER_LEN);
+    }
+    dump.header = fields(&HEADER_FIELDS, position, &header);
+    dump.computed_checksum = checksum(reader, position, &header)?;
+    decode_header(&mut dump);
+    if offset_record || len < header_len {
+        return Ok(Some(dump));
+    }
+    let record_header: RecordHeader = unsafe { std::ptr::read_unaligned(header.as_ptr().cast()) };
+
+    reader.seek(SeekFrom::Start(position + header_len as u64))?;
+    let (name, padding) = read_name(reader)?;
+    dump.name = Some(name);
+    dump.name_padding = padding;
+    if record_header.mode & 0xF000 == 0xA000 {
+        dump.symlink = Some(read_name(reader)?.0);
+    }
+
+    let acl_position = reader.stream_position()?;
+    let mut trailer = vec![0u8; std::mem::size_of::<RecordAcl>()];
+    let len = read_up_to(reader, &mut trailer)?;
+    trailer.truncate(len);
+    dump.acl = fields(&ACL_FIELDS, acl_position, &trailer);
+    for field in &mut dump.acl {
+        field.decoded = match field.name {
+            "acl_payload_bytes" => "start of the ACL payload".to_string(),
+            _ => field.value().to_string(),
+        };
+    }
+    if len < std::mem::size_of::<RecordAcl>() {
+        return Ok(Some(dump));
+    }
+    let record_acl: RecordAcl = unsafe { std::ptr::read_unaligned(trailer.as_ptr().cast()) };
+
+    let mut payload_start = reader.stream_position()?;
+    if record_header.mode & S_IXACL > 0 && record_acl.acl_len > 0 {
+        let acl_len = u64::from(record_acl.acl_len);
+        let inline = acl_len.min(TRAILER_INLINE_ACL_BYTES as u64) as usize;
+        dump.acl_payload = record_acl.acl_payload_bytes[..inline].to_vec();
+        let extra = acl_len.saturating_sub(TRAILER_INLINE_ACL_BYTES as u64);
+        reader
+            .take(extra.min(MAX_ACL_LEN))
+            .read_to_end(&mut dump.acl_payload)?;
+        // The ACL payload is padded to 16 bytes
+        payload_start += extra + acl_len.next_multiple_of(16) - acl_len;
+    }
+    let payload_start = payload_start.next_multiple_of(8);
+    dump.payload_start = Some(payload_start);
+    let payload_len = if record_header.file_size() > 0 {
+        record_header.payload_size()
+    } else {
+        0
+    };
+    // A corrupt payload size may point behind any position.
+    dump.payload_end = payload_start.checked_add(payload_len);
+    Ok(Some(dump))
+}
+
+/// Split the available `bytes` into the `layout` of fields stored at `position`.
+fn fields(layout: &[(&'static str, usize, usize)], position: u64, bytes: &[u8]) -> Vec<Field> {
+    layout
+        .iter()
+        .filter(|(_, offset, size)| offset + size <= bytes.len())
+        .map(|&(name, offset, size)| Field {
+            name,
+            offset: position + offset as u64,
+            raw: bytes[offset..offset + size].to_vec(),
+            decoded: String::new(),
+        })
+        .collect()
+}
+
+/// Compute the checksum over the length announced by the first byte of the record.
+fn checksum<R: Read + Seek>(reader: &mut R, position: u64, header: &[u8]) -> Result<Option<u16>> {
+    let Some(&units) = header.first() else {
+        return Ok(None);
+    };
+    let mut block = vec![0u8; usize::from(units) * 8];
+    reader.seek(SeekFrom::Start(position))?;
+    let len = read_up_to(reader, &mut block)?;
+    block.truncate(len);
+    Ok(compute_file_header_checksum(&block))
+}
+
+/// Read a NUL terminated name stored in 8-byte units. Returns the name and the bytes behind the
+/// NUL up to the next 8-byte boundary.
+fn read_name<R: Read>(reader: &mut R) -> Result<(String, Vec<u8>)> {
+    let mut name = vec![];
+    loop {
+        let mut unit = [0u8; 8];
+        let len = read_up_to(reader, &mut unit)?;
+        if let Some(end) = unit[..len].iter().position(|&byte| byte == 0) {
+            name.extend_from_slice(&unit[..end]);
+            return Ok((
+                String::from_utf8_lossy(&name).into_owned(),
+                unit[end + 1..len].to_vec(),
+            ));
+        }
+        name.extend_from_slice(&unit[..len]);
+        if len < unit.len() || name.len() >= MAX_NAME_LEN {
+            return Ok((String::from_utf8_lossy(&name).into_owned(), vec![]));
+        }
+    }
+}
+
+/// Describe the values of the header fields of `dump`.
+fn decode_header(dump: &mut RecordDump) {
+    let computed = dump.computed_checksum;
+    for field in &mut dump.header {
+        let value = field.value();
+        field.decoded = match field.name {
+            "unk00" => format!("{} bytes of header and name", value * 8),
+            "unk01" => match value as u8 {
+                RECORD_FORMAT_MARKER => "file record".to_string(),
+                OFFSET_FORMAT_MARKER => "offset record".to_string(),
+                _ => "unknown format".to_string(),
+            },
+            "magic" if value as u16 == HUFFMAN_MAGIC => "huffman compressed".to_string(),
+            "magic" if HEADER_MAGICS.contains(&(value as u16)) => "valid".to_string(),
+            "magic" => "invalid".to_string(),
+            "unk04" => {
+                let computed = computed.map_or("invalid".to_string(), |c| format!("{c:#06x}"));
+                format!(
+                    "checksum {:#06x} (computed {computed}), {} links",
+                    value & 0xFFFF,
+                    value >> 16
+                )
+            }
+            "unk08" => format!("inode {value}"),
+            "mode" => format!("{}", Mode::from(value)),
+            "atime" | "mtime" | "time24" => DateTime::from_timestamp(i64::from(value), 0)
+                .map_or(value.to_string(), |date| {
+                    date.format("%Y-%m-%d %H:%M:%S UTC").to_string()
+                }),
+            "unk28" => format!("device major {value}"),
+            "unk2_c" => format!("device minor {value}"),
+            "unk30" => "file size, upper 32 bits".to_string(),
+            "unk34" => "reserved".to_string(),
+            "unk3_c" => "payload size, upper 32 bits".to_string(),
+            _ => value.to_string(),
+        };
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+
+    use super::*;
+    use crate::writer::archive_with_files;
+
+    fn sample() -> Cursor<&'static [u8]> {
+        Cursor::new(include_bytes!("../../resources/test/test.bff"))
+    }
+
+    #[test]
+    fn dumps_records_of_sample_archive() {
+        let dumps = inspect(&mut sample()).unwrap();
+
+        assert_eq!(dumps.len(), 5);
+        let file = &dumps[1];
+        assert_eq!(file.offset, 0xb8);
+        assert_eq!(file.name.as_deref(), Some("backup/file.txt"));
+        assert!(file.error.is_none());
+        let checksum = file.field("unk04").unwrap();
+        assert_eq!(checksum.hex(), "0x0001076e");
+        assert_eq!(
+            checksum.decoded,
+            "checksum 0x076e (computed 0x076e), 1 links"
+        );
+        assert_eq!(file.field("unk28").unwrap().decoded, "device major 10");
+        assert_eq!(file.acl.len(), 5);
+        assert_eq!(file.payload_end.unwrap() - file.payload_start.unwrap(), 3);
+
+        let offset_record = &dumps[4];
+        assert_eq!(offset_record.offset, 584);
+        assert_eq!(offset_record.header.len(), 4);
+        assert_eq!(
+            offset_record.field("unk01").unwrap().decoded,
+            "offset record"
+        );
+        assert!(offset_record.name.is_none());
+    }
+
+    #[test]
+    fn dumps_rejected_records() {
+        let mut data =
+            archive_with_files(&[("./first.txt", b"first"), ("./second.txt", b"second")]);
+        // Invalid magic number of the first record
+        data[74..76].copy_from_slice(&[0x12, 0x34]);
+
+        let dumps = inspect(&mut Cursor::new(data)).unwrap();
+
+        assert_eq!(dumps[0].name.as_deref(), Some("./first.txt"));
+        assert_eq!(dumps[0].field("magic").unwrap().decoded, "invalid");
+        assert_eq!(
+            dumps[0].error.as_deref(),
+            Some(
+                crate::Error::InvalidRecordMagic(0x3412)
+                    .to_string()
+                    .as_str()
+            )
+        );
+        assert_eq!(dumps[1].name.as_deref(), Some("./second.txt"));
+        assert!(dumps[1].error.is_none());
+    }
+
+    #[test]
+    fn dumps_record_with_overflowing_payload_size() {
+        let mut data = include_bytes!("../../resources/test/test.bff").to_vec();
+        // Payload size of `backup/file.txt` including its upper 32 bits
+        data[0xb8 + 56..0xb8 + 64].fill(0xFF);
+
+        let dumps = inspect(&mut Cursor::new(data)).unwrap();
+
+        assert!(dumps[1].payload_start.is_some());
+        assert_eq!(dumps[1].payload_end, None);
+        assert_eq!(
+            dumps[1].error.as_deref(),
+            Some(crate::Error::InvalidRecord.to_string().as_str())
+        );
+    }
+}
@@ -34,14 +34,42 @@
 //!     Ok(())
 //! }
 //! ```
+//!
+//! Create an archive from a directory:
+//!
+//! ```rust
+//! use std::fs::File;
+//! use bfflib::{writer::ArchiveWriter, Result};
+//!
+//! fn example() -> Result<()> {
+//!     let mut writer = ArchiveWriter::new(File::create("file.bff")?)?;
+//!     writer.append_dir_all("input_dir", "./input_dir")?;
+//!     writer.finish()?;
+//!     Ok(())
+//! }
+//! ```
 
 pub mod acl;
 pub mod archive;
 pub mod attribute;
 pub mod bff;
+pub mod carve;
+pub mod charset;
+pub mod compression;
 pub mod error;
 mod extract;
 pub mod huffman;
+pub mod info;
+pub mod inspect;
+pub mod lzw;
+pub mod mksysb;
+pub mod pack;
+pub mod research;
+pub mod stream;
+pub mod tape;
 pub mod util;
+pub mod validate;
+pub mod volume;
+pub mod writer;
 
 pub use error::{Error, Result};
@@ -0,0 +1,299 @@
+//! Decoding of files compressed by the Unix `compress` command (.Z files)
+//!
+//! The data is LZW encoded with code widths growing from 9 bits up to the maximum stored in the
+//! header. Codes are written in groups of eight. Whenever the code width changes or the table is
+//! cleared, the rest of the current group is padding.
+
+use std::io::{ErrorKind, Read};
+
+use crate::{Error, Result};
+
+/// All compressed files start with these bytes.
+pub const LZW_MAGIC: [u8; 2] = [0x1F, 0x9D];
+
+/// Flag in the header set if the stream may contain [CLEAR_CODE].
+const BLOCK_MODE: u8 = 0x80;
+/// Mask selecting the maximum code width in the header.
+const MAX_BITS_MASK: u8 = 0x1F;
+/// Code width at the start of the stream and after clearing the table.
+const INIT_BITS: u32 = 9;
+/// Largest code width supported by `compress`.
+const MAX_BITS: u32 = 16;
+/// Code resetting the table in block mode.
+const CLEAR_CODE: usize = 256;
+/// Amount of codes written with the same width before padding is possible.
+const GROUP_SIZE: u32 = 8;
+/// Size of the blocks read from the compressed input.
+const INPUT_BUFFER_SIZE: usize = 64 * 1024;
+
+/// A decoder for files compressed by the Unix `compress` command.
+pub struct LzwDecoder<R> {
+    /// Source reader containing compressed data
+    reader: R,
+    /// Largest code width of the stream
+    max_bits: u32,
+    /// [CLEAR_CODE] resets the table
+    block_mode: bool,
+    /// Current code width
+    n_bits: u32,
+    /// Largest code readable with the current width
+    max_code: usize,
+    /// Next table entry to define
+    free_entry: usize,
+    /// Code of the string an entry extends
+    prefix: Vec<u16>,
+    /// Last byte of the string of an entry
+    suffix: Vec<u8>,
+    /// Previously decoded code
+    old_code: Option<usize>,
+    /// First byte of the previously decoded string
+    final_byte: u8,
+    /// Amount of codes read since the last width change
+    group_codes: u32,
+    /// Block of compressed input
+    input: Box<[u8]>,
+    /// Position of the next unused byte in `input`
+    input_pos: usize,
+    /// Amount of valid bytes in `input`
+    input_len: usize,
+    /// Input bits not decoded yet. The next code starts at the lowest bit.
+    bit_buf: u64,
+    bit_count: u32,
+    /// Decoded string not returned yet
+    output: Vec<u8>,
+    /// Position of the next byte to return in `output`
+    output_pos: usize,
+}
+
+impl<R: Read> LzwDecoder<R> {
+    /// Create a new instance of `LzwDecoder` by providing a reader.
+    ///
+    /// It will read the magic number and the header flags. If this fails or the header is
+    /// invalid, an error will be returned.
+    pub fn new(mut reader: R) -> Result<Self> {
+        let mut header = [0u8; 3];
+        reader.read_exact(&mut header)?;
+        if header[..2] != LZW_MAGIC {
+            return Err(Error::InvalidLzwMagic(u16::from_be_bytes([
+                header[0], header[1],
+            ])));
+        }
+        let max_bits = u32::from(header[2] & MAX_BITS_MASK);
+        if !(INIT_BITS..=MAX_BITS).contains(&max_bits) {
+            return Err(Error::InvalidLzwData);
+        }
+        let block_mode = header[2] & BLOCK_MODE != 0;
+        Ok(Self {
+            reader,
+            max_bits,
+            block_mode,
+            n_bits: INIT_BITS,
+            max_code: (1 << INIT_BITS) - 1,
+            free_entry: if block_mode { CLEAR_CODE + 1 } else { 256 },
+            prefix: vec![0; 1 << max_bits],
+            suffix: vec![0; 1 << max_bits],
+            old_code: None,
+            final_byte: 0,
+            group_codes: 0,
+            input: vec![0; INPUT_BUFFER_SIZE].into_boxed_slice(),
+            input_pos: 0,
+            input_len: 0,
+            bit_buf: 0,
+            bit_count: 0,
+            output: vec![],
+            output_pos: 0,
+        })
+    }
+
+    /// Read the next code. Returns `None` at the end of the input.
+    fn read_code(&mut self) -> std::io::Result<Option<usize>> {
+        while self.bit_count < self.n_bits {
+            if self.input_pos == self.input_len {
+                self.input_len = loop {
+                    match self.reader.read(&mut self.input) {
+                        Err(error) if error.kind() == ErrorKind::Interrupted => continue,
+                        result => break result?,
+                    }
+                };
+                self.input_pos = 0;
+                if self.input_len == 0 {
+                    // Remaining bits are padding of the last group.
+                    return Ok(None);
+                }
+            }
+            self.bit_buf |= u64::from(self.input[self.input_pos]) << self.bit_count;
+            self.input_pos += 1;
+            self.bit_count += 8;
+        }
+        let code = (self.bit_buf & ((1 << self.n_bits) - 1)) as usize;
+        self.bit_buf >>= self.n_bits;
+        self.bit_count -= self.n_bits;
+        self.group_codes += 1;
+        Ok(Some(code))
+    }
+
+    /// Skip the padding up to the end of the current group of codes.
+    fn skip_group_padding(&mut self) -> std::io::Result<()> {
+        while !self.group_codes.is_multiple_of(GROUP_SIZE) {
+            if self.read_code()?.is_none() {
+                break;
+            }
+        }
+        self.group_codes = 0;
+        Ok(())
+    }
+
+    /// Decode the next string into `output`. Returns `false` at the end of the input.
+    fn decode_next(&mut self) -> std::io::Result<bool> {
+        loop {
+            if self.free_entry > self.max_code {
+                self.skip_group_padding()?;
+                self.n_bits += 1;
+                self.max_code = if self.n_bits == self.max_bits {
+                    1 << self.max_bits
+                } else {
+                    (1 << self.n_bits) - 1
+                };
+            }
+            let Some(code) = self.read_code()? else {
+                return Ok(false);
+            };
+            self.output.clear();
+            self.output_pos = 0;
+
+            let Some(old_code) = self.old_code else {
+                if code >= 256 {
+                    return Err(std::io::Error::other(Error::InvalidLzwData));
+                }
+                self.final_byte = code as u8;
+                self.output.push(self.final_byte);
+                self.old_code = Some(code);
+                return Ok(true);
+            };
+
+            if code == CLEAR_CODE && self.block_mode {
+                self.free_entry = CLEAR_CODE;
+                self.skip_group_padding()?;
+                self.n_bits = INIT_BITS;
+                self.max_code = (1 << INIT_BITS) - 1;
+                continue;
+            }
+
+            // The string is collected backwards starting at its last byte.
+            let mut current = code;
+            if code >= self.free_entry {
+                if code > self.free_entry {
+                    return Err(std::io::Error::other(Error::InvalidLzwData));
+                }
+                // The code is defined by this very step: the previous string plus its first byte.
+                self.output.push(self.final_byte);
+                current = old_code;
+            }
+            while current >= 256 {
+                if self.output.len() >= self.prefix.len() {
+                    return Err(std::io::Error::other(Error::InvalidLzwData));
+                }
+                self.output.push(self.suffix[current]);
+                current = usize::from(self.prefix[current]);
+            }
+            self.final_byte = current as u8;
+            self.output.push(self.final_byte);
+            self.output.reverse();
+
+            if self.free_entry < self.prefix.len() {
+                self.prefix[self.free_entry] = old_code as u16;
+                self.suffix[self.free_entry] = self.final_byte;
+                self.free_entry += 1;
+            }
+            self.old_code = Some(code);
+            return Ok(true);
+        }
+    }
+}
+
+impl<R: Read> Read for LzwDecoder<R> {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        let mut written = 0;
+        while written < buf.len() {
+            if self.output_pos == self.output.len() && !self.decode_next()? {
+                break;
+            }
+            let available = &self.output[self.output_pos..];
+            let len = available.len().min(buf.len() - written);
+            buf[written..written + len].copy_from_slice(&available[..len]);
+            self.output_pos += len;
+            written += len;
+        }
+        Ok(written)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{fs, io::Read, path::PathBuf};
+
+    use super::*;
+
+    fn get_resources_path() -> PathBuf {
+        let mut dir = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+        dir.push("../resources/test");
+        dir
+    }
+
+    fn decode(data: &[u8]) -> std::io::Result<Vec<u8>> {
+        let mut decoder = LzwDecoder::new(data).map_err(std::io::Error::other)?;
+        let mut result = vec![];
+        decoder.read_to_end(&mut result)?;
+        Ok(result)
+    }
+
+    #[test]
+    fn decode_file() {
+        let dir = get_resources_path();
+        let compressed = fs::read(dir.join("test.bff.Z")).unwrap();
+        let expected = fs::read(dir.join("test.bff")).unwrap();
+
+        assert_eq!(decode(&compressed).unwrap(), expected);
+    }
+
+    #[test]
+    fn decode_file_with_width_changes_and_clears() {
+        // Compressed with a maximum of 10 bits, so the table is cleared several times.
+        let dir = get_resources_path();
+        let compressed = fs::read(dir.join("huffman_decoded.bin.Z")).unwrap();
+        let expected = fs::read(dir.join("huffman_decoded.bin")).unwrap();
+
+        assert_eq!(compressed[2], BLOCK_MODE | 10);
+        assert_eq!(decode(&compressed).unwrap(), expected);
+    }
+
+    #[test]
+    fn decode_empty_stream() {
+        assert!(decode(&[0x1F, 0x9D, 0x90]).unwrap().is_empty());
+    }
+
+    #[test]
+    fn rejects_invalid_magic() {
+        let result = LzwDecoder::new([0x1F, 0x8B, 0x90].as_slice());
+
+        assert!(matches!(result, Err(Error::InvalidLzwMagic(0x1F8B))));
+    }
+
+    #[test]
+    fn rejects_invalid_max_bits() {
+        let result = LzwDecoder::new([0x1F, 0x9D, 0x91].as_slice());
+
+        assert!(matches!(result, Err(Error::InvalidLzwData)));
+    }
+
+    #[test]
+    fn rejects_undefined_code() {
+        // 'a' followed by code 300 while the next free entry is 257
+        let code = 97u32 | (300 << 9);
+        let mut data = vec![0x1F, 0x9D, 0x90];
+        data.extend_from_slice(&code.to_le_bytes()[..3]);
+
+        let error = decode(&data).unwrap_err();
+        assert!(matches!(Error::from(error), Error::InvalidLzwData));
+    }
+}
@@ -0,0 +1,182 @@
+//! Locating the volume group backup in mksysb and savevg images
+//!
+//! A mksysb tape holds four images: the boot image, the installation image created by
+//! `mkinsttape`, a dummy table of contents and the backup of rootvg. All but the boot image are
+//! BFF archives. Images written to a file by `mksysb` or `savevg` contain the backup archive only.
+//!
+//! The image layout isn't parsed. Tape copies made with `dd` lose the file marks separating the
+//! images, and the size of the boot image varies, so the backup is found by a heuristic instead:
+//! every archive of the image is searched by its magic number like [TapeArchives::search] does and
+//! read completely. The backup is recognized by its records: a mksysb backup contains
+//! `./image.data`, a savevg backup contains the volume group data below `./tmp/vgdata`. As the
+//! installation image contains `./image.data` too, the last matching archive of the image is used.
+//!
+//! The records read while searching are kept, so opening the backup doesn't read it again.
+
+use std::{
+    fmt::Display,
+    io::{Read, Seek},
+    path::Path,
+};
+
+use crate::{
+    Result,
+    archive::{Archive, Record},
+    stream::OffsetReader,
+    tape::{TapeArchive, TapeArchives},
+};
+
+/// Kind of a volume group backup.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BackupKind {
+    /// Backup of rootvg created by `mksysb`
+    Mksysb,
+    /// Backup of another volume group created by `savevg`
+    Savevg,
+}
+
+impl BackupKind {
+    /// Detect the kind of backup by the records of an archive.
+    ///
+    /// Returns `None` if the archive isn't a volume group backup.
+    pub fn from_records(records: &[Record]) -> Option<Self> {
+        let paths = || {
+            records.iter().map(|record| {
+                record
+                    .filename()
+                    .strip_prefix(".")
+                    .unwrap_or(record.filename())
+            })
+        };
+        if paths().any(|path| path == Path::new("image.data")) {
+            Some(Self::Mksysb)
+        } else if paths().any(|path| path.starts_with("tmp/vgdata")) {
+            Some(Self::Savevg)
+        } else {
+            None
+        }
+    }
+}
+
+impl Display for BackupKind {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Mksysb => write!(f, "mksysb"),
+            Self::Savevg => write!(f, "savevg"),
+        }
+    }
+}
+
+/// A volume group backup found in a mksysb or savevg image.
+#[derive(Debug, Clone)]
+pub struct VolumeGroupBackup {
+    /// Kind of the backup
+    pub kind: BackupKind,
+    /// The backup archive and its position in the image
+    pub archive: TapeArchive,
+}
+
+impl VolumeGroupBackup {
+    /// Opens the backup in the image `reader` to read or extract its files. See
+    /// [TapeArchive::open].
+    pub fn open<R: Read + Seek>(&self, reader: R) -> Result<Archive<OffsetReader<R>>> {
+        self.archive.open(reader)
+    }
+}
+
+/// Find the volume group backup in a mksysb or savevg image.
+///
+/// Data in front of the archives, like the boot image, is skipped. All archives of the image are
+/// read to check their records, see the [module docs](self). Returns `None` if no archive of the
+/// image is a volume group backup.
+pub fn find_backup<R: Read + Seek>(reader: &mut R) -> Result<Option<VolumeGroupBackup>> {
+    let mut backup = None;
+    for archive in TapeArchives::search(reader) {
+        let archive = archive?;
+        if let Some(kind) = BackupKind::from_records(&archive.records) {
+            backup = Some(VolumeGroupBackup { kind, archive });
+        }
+    }
+    Ok(backup)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+
+    use super::*;
+    use crate::{tape::TAPE_BLOCK_SIZE, writer::archive_with_files};
+
+    fn append_image(image: &mut Vec<u8>, data: &[u8]) {
+        image.extend_from_slice(data);
+        image.resize(image.len().next_multiple_of(TAPE_BLOCK_SIZE as usize), 0);
+    }
+
+    /// A mksysb tape image. Returns the image and the offset of the rootvg backup.
+    fn mksysb_image() -> (Vec<u8>, u64) {
+        let mut image = vec![];
+        append_image(&mut image, &[0x5A; 3000]);
+        append_image(
+            &mut image,
+            &archive_with_files(&[
+                ("./image.data", b"image.data of installation"),
+                ("./tapeblksz", b"512"),
+            ]),
+        );
+        append_image(&mut image, &archive_with_files(&[("./tapeblksz", b"512")]));
+        let offset = image.len() as u64;
+        append_image(
+            &mut image,
+            &archive_with_files(&[
+                ("./bosinst.data", b"bosinst.data"),
+                ("./image.data", b"image.data of rootvg"),
+                ("./etc/hosts", b"127.0.0.1 loopback"),
+            ]),
+        );
+        (image, offset)
+    }
+
+    #[test]
+    fn finds_rootvg_backup_of_mksysb_tape() {
+        let (image, offset) = mksysb_image();
+        let mut reader = Cursor::new(image);
+        let backup = find_backup(&mut reader).unwrap().unwrap();
+
+        assert_eq!(backup.kind, BackupKind::Mksysb);
+        assert_eq!(backup.archive.offset, offset);
+
+        let mut archive = backup.open(reader).unwrap();
+        let mut content = String::new();
+        archive
+            .file("./etc/hosts")
+            .unwrap()
+            .unwrap()
+            .read_to_string(&mut content)
+            .unwrap();
+        assert_eq!(content, "127.0.0.1 loopback");
+    }
+
+    #[test]
+    fn finds_savevg_backup() {
+        let image = archive_with_files(&[
+            ("./tmp/vgdata/datavg/datavg.data", b"vg data"),
+            ("./data/file.txt", b"content"),
+        ]);
+        let backup = find_backup(&mut Cursor::new(image)).unwrap().unwrap();
+
+        assert_eq!(backup.kind, BackupKind::Savevg);
+        assert_eq!(backup.archive.offset, 0);
+    }
+
+    #[test]
+    fn ignores_other_archives() {
+        let image = archive_with_files(&[("./usr/bin/tool", b"tool")]);
+
+        assert!(find_backup(&mut Cursor::new(image)).unwrap().is_none());
+        assert!(
+            find_backup(&mut Cursor::new(vec![0u8; 2048]))
+                .unwrap()
+                .is_none()
+        );
+    }
+}
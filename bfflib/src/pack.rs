@@ -0,0 +1,130 @@
+//! Reading and writing of standalone Unix `pack` (.z) files
+//!
+//! A packed file consists of the magic number, the original file size as big endian 32 bit
+//! integer and the same Huffman encoded stream that BFF uses for compressed records.
+
+use std::io::{Read, Seek, SeekFrom, Write};
+
+use crate::{
+    Error, Result,
+    huffman::{HuffmanDecoder, HuffmanEncoder},
+};
+
+/// All packed files start with these bytes.
+pub const PACK_MAGIC: [u8; 2] = [0x1F, 0x1E];
+
+/// A reader decoding a packed file.
+pub struct PackReader<R> {
+    decoder: HuffmanDecoder<R>,
+    original_size: u32,
+}
+
+impl<R: Read> PackReader<R> {
+    /// Create a new instance of `PackReader` by providing a reader.
+    ///
+    /// It will read the magic number, the original size and the Huffman header. If this fails or the
+    /// magic number is invalid, an error will be returned.
+    pub fn new(mut reader: R) -> Result<Self> {
+        let mut header = [0u8; 6];
+        reader.read_exact(&mut header)?;
+        if header[..2] != PACK_MAGIC {
+            return Err(Error::InvalidPackMagic(u16::from_be_bytes([
+                header[0], header[1],
+            ])));
+        }
+        let original_size = u32::from_be_bytes([header[2], header[3], header[4], header[5]]);
+        Ok(Self {
+            decoder: HuffmanDecoder::with_expected_len(reader, u64::from(original_size))?,
+            original_size,
+        })
+    }
+
+    /// Returns the size of the unpacked data as stored in the file header.
+    pub fn original_size(&self) -> u32 {
+        self.original_size
+    }
+}
+
+impl<R: Read> Read for PackReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        self.decoder.read(buf)
+    }
+}
+
+impl<R: Read + Seek> Seek for PackReader<R> {
+    fn seek(&mut self, pos: SeekFrom) -> std::io::Result<u64> {
+        self.decoder.seek(pos)
+    }
+}
+
+/// A writer creating a packed file.
+///
+/// The original size is part of the header, so all written data is buffered until
+/// [PackWriter::finish] is called.
+pub struct PackWriter<W: Write> {
+    writer: W,
+    data: Vec<u8>,
+}
+
+impl<W: Write> PackWriter<W> {
+    /// Create a new instance of `PackWriter` by providing a writer.
+    pub fn new(writer: W) -> Self {
+        Self {
+            writer,
+            data: vec![],
+        }
+    }
+
+    /// Write the packed file and return the underlying writer.
+    pub fn finish(mut self) -> Result<W> {
+        let original_size = u32::try_from(self.data.len()).map_err(|_| Error::FileToBig)?;
+        self.writer.write_all(&PACK_MAGIC)?;
+        self.writer.write_all(&original_size.to_be_bytes())?;
+        let mut encoder = HuffmanEncoder::new(&mut self.writer);
+        encoder.write_all(&self.data)?;
+        encoder.finish()?;
+        Ok(self.writer)
+    }
+}
+
+impl<W: Write> Write for PackWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        self.data.extend_from_slice(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::{Read, Write};
+
+    use super::*;
+
+    #[test]
+    fn pack_round_trip() {
+        let data = b"packed with the classic unix pack format\n".repeat(16);
+        let mut writer = PackWriter::new(vec![]);
+        writer.write_all(&data).unwrap();
+        let packed = writer.finish().unwrap();
+
+        assert_eq!(packed[..2], PACK_MAGIC);
+        assert_eq!(packed[2..6], (data.len() as u32).to_be_bytes());
+
+        let mut reader = PackReader::new(packed.as_slice()).unwrap();
+        assert_eq!(reader.original_size() as usize, data.len());
+        let mut result = vec![];
+        reader.read_to_end(&mut result).unwrap();
+        assert_eq!(result, data);
+    }
+
+    #[test]
+    fn rejects_invalid_magic() {
+        let result = PackReader::new([0x1F, 0x8B, 0, 0, 0, 0, 1, 0, 0].as_slice());
+
+        assert!(matches!(result, Err(Error::InvalidPackMagic(0x1F8B))));
+    }
+}
@@ -0,0 +1,220 @@
+//! Statistics of the unknown header fields across many archives
+//!
+//! A [Corpus] collects the values of the fields named `unk*` of the [FileHeader] and the
+//! [RecordHeader] of many archives. Values of record fields are counted per [EntryKind], so
+//! correlations between a field and the kind of record show up. Every value keeps a few examples
+//! pointing to the archive and the offset it was found at.
+
+use std::{
+    collections::BTreeMap,
+    io::{Read, Seek},
+    path::{Path, PathBuf},
+};
+
+use crate::{
+    Error, Result,
+    archive::Archive,
+    bff::{FileHeader, RecordHeader},
+};
+
+/// Amount of examples kept per value.
+pub const MAX_EXAMPLES: usize = 3;
+
+/// Context of the values of [FileHeader] fields.
+const ARCHIVE_CONTEXT: &str = "archive";
+
+/// A location a value was found at.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Example {
+    /// Path of the archive
+    pub path: PathBuf,
+    /// Position of the field in the archive
+    pub offset: u64,
+}
+
+/// How often a field had a value in a context.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Observation {
+    /// Name of the field, e.g. `RecordHeader.unk00`
+    pub field: &'static str,
+    /// Value of the field
+    pub value: u32,
+    /// Kind of record the value was found in, or `archive` for fields of the [FileHeader]
+    pub context: String,
+    /// Amount of occurrences
+    pub count: usize,
+    /// The first occurrences, up to [MAX_EXAMPLES]
+    pub examples: Vec<Example>,
+}
+
+/// Statistics of the unknown header fields of a collection of archives.
+#[derive(Debug, Default)]
+pub struct Corpus {
+    observations: BTreeMap<(&'static str, u32, String), Observation>,
+    archives: usize,
+}
+
+impl Corpus {
+    /// Create an empty corpus.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Add the archive read from `reader` and found at `path`.
+    ///
+    /// Returns `false` if the data isn't a BFF archive.
+    pub fn add_archive<R: Read + Seek>(&mut self, path: &Path, reader: R) -> Result<bool> {
+        let archive = match Archive::scan(reader) {
+            Ok(archive) => archive,
+            Err(Error::InvalidFileMagic(_)) => return Ok(false),
+            Err(Error::IoError(error)) if error.kind() == std::io::ErrorKind::UnexpectedEof => {
+                return Ok(false);
+            }
+            Err(error) => return Err(error),
+        };
+        self.archives += 1;
+
+        let header = archive.header();
+        for (field, offset, value) in file_header_fields(header) {
+            self.observe(field, value, ARCHIVE_CONTEXT, path, offset);
+        }
+        for record in archive.records() {
+            let context = record.kind().to_string();
+            for (field, offset, value) in record_header_fields(record.header()) {
+                let offset = record.header_position() + offset;
+                self.observe(field, value, &context, path, offset);
+            }
+        }
+        Ok(true)
+    }
+
+    /// Returns the amount of archives added.
+    pub fn archives(&self) -> usize {
+        self.archives
+    }
+
+    /// Returns all observations ordered by field, value and context.
+    pub fn observations(&self) -> impl Iterator<Item = &Observation> {
+        self.observations.values()
+    }
+
+    fn observe(
+        &mut self,
+        field: &'static str,
+        value: u32,
+        context: &str,
+        path: &Path,
+        offset: u64,
+    ) {
+        let observation = self
+            .observations
+            .entry((field, value, context.to_string()))
+            .or_insert_with(|| Observation {
+                field,
+                value,
+                context: context.to_string(),
+                count: 0,
+                examples: vec![],
+            });
+        observation.count += 1;
+        if observation.examples.len() < MAX_EXAMPLES {
+            observation.examples.push(Example {
+                path: path.to_path_buf(),
+                offset,
+            });
+        }
+    }
+}
+
+/// Returns name, position and value of the unknown fields of a [FileHeader].
+fn file_header_fields(header: &FileHeader) -> [(&'static str, u64, u32); 8] {
+    [
+        ("FileHeader.unk10", 16, header.unk10),
+        ("FileHeader.unk1_c", 28, header.unk1_c),
+        ("FileHeader.unk20", 32, header.unk20),
+        ("FileHeader.unk2_c", 44, header.unk2_c),
+        ("FileHeader.unk30", 48, header.unk30),
+        ("FileHeader.unk3_c", 60, header.unk3_c),
+        ("FileHeader.unk40", 64, header.unk40),
+        ("FileHeader.unk44", 68, header.unk44),
+    ]
+}
+
+/// Returns name, position and value of the unknown fields of a [RecordHeader].
+///
+/// The checksum in `unk04` differs for every record, so only its link count is collected.
+fn record_header_fields(header: &RecordHeader) -> [(&'static str, u64, u32); 9] {
+    [
+        ("RecordHeader.unk00", 0, u32::from(header.unk00)),
+        ("RecordHeader.unk01", 1, u32::from(header.unk01)),
+        (
+            "RecordHeader.unk04.links",
+            6,
+            u32::from(header.link_count()),
+        ),
+        ("RecordHeader.unk08", 8, header.unk08),
+        ("RecordHeader.unk28", 40, header.unk28),
+        ("RecordHeader.unk2_c", 44, header.unk2_c),
+        ("RecordHeader.unk30", 48, header.unk30),
+        ("RecordHeader.unk34", 52, header.unk34),
+        ("RecordHeader.unk3_c", 60, header.unk3_c),
+    ]
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+
+    use super::*;
+
+    fn corpus() -> Corpus {
+        let mut corpus = Corpus::new();
+        for name in ["test.bff", "acl_aixc_nfs4.bff"] {
+            let path = Path::new("../resources/test").join(name);
+            let data = std::fs::read(&path).unwrap();
+            assert!(corpus.add_archive(&path, Cursor::new(data)).unwrap());
+        }
+        corpus
+    }
+
+    fn observation<'a>(
+        corpus: &'a Corpus,
+        field: &str,
+        value: u32,
+        context: &str,
+    ) -> Option<&'a Observation> {
+        corpus
+            .observations()
+            .find(|o| o.field == field && o.value == value && o.context == context)
+    }
+
+    #[test]
+    fn counts_values_per_kind_of_record() {
+        let corpus = corpus();
+
+        assert_eq!(corpus.archives(), 2);
+        let unk10 = observation(&corpus, "FileHeader.unk10", 0x7FFF_FFFF, "archive").unwrap();
+        assert_eq!(unk10.count, 2);
+        // Both archives have a directory numbered 0xFFFF_FFFF and files numbered 0.
+        let directories = observation(&corpus, "RecordHeader.unk08", 0xFFFF_FFFF, "directory");
+        assert_eq!(directories.unwrap().count, 2);
+        let files = observation(&corpus, "RecordHeader.unk08", 0, "file").unwrap();
+        assert_eq!(files.count, 4);
+        assert_eq!(files.examples.len(), MAX_EXAMPLES);
+        assert_eq!(files.examples[0].offset, 0xb8 + 8);
+        assert!(files.examples[0].path.ends_with("test.bff"));
+        assert!(observation(&corpus, "RecordHeader.unk08", 0, "directory").is_none());
+    }
+
+    #[test]
+    fn skips_other_files() {
+        let mut corpus = Corpus::new();
+
+        let added = corpus
+            .add_archive(Path::new("notes.txt"), Cursor::new(b"some notes"))
+            .unwrap();
+
+        assert!(!added);
+        assert_eq!(corpus.archives(), 0);
+    }
+}
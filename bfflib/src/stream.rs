@@ -0,0 +1,226 @@
+//! Reading archives from sources which can't seek, like pipes or stdin, or which are embedded in
+//! larger files
+
+use std::io::{self, Read, Seek, SeekFrom};
+
+/// A reader tracking its position which implements [Seek] for forward movement only.
+///
+/// Seeking forward reads and discards the data in between. Seeking backwards or relative to the
+/// end returns an error of kind [io::ErrorKind::Unsupported]. This is enough to iterate an
+/// archive by [RecordIter](crate::archive::RecordIter), which reads records strictly in order.
+pub struct ForwardReader<R> {
+    reader: R,
+    position: u64,
+}
+
+impl<R: Read> ForwardReader<R> {
+    /// Create a new instance of `ForwardReader` by providing a reader.
+    pub fn new(reader: R) -> Self {
+        Self {
+            reader,
+            position: 0,
+        }
+    }
+
+    /// Returns the underlying reader.
+    pub fn into_inner(self) -> R {
+        self.reader
+    }
+}
+
+impl<R: Read> Read for ForwardReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let read = self.reader.read(buf)?;
+        self.position += read as u64;
+        Ok(read)
+    }
+}
+
+impl<R: Read> Seek for ForwardReader<R> {
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        let target = match pos {
+            SeekFrom::Start(offset) => Some(offset),
+            SeekFrom::Current(offset) => self.position.checked_add_signed(offset),
+            SeekFrom::End(_) => None,
+        }
+        .filter(|&target| target >= self.position)
+        .ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::Unsupported,
+                "a forward-only stream can't seek backwards or from the end",
+            )
+        })?;
+
+        let len = target - self.position;
+        let skipped = io::copy(&mut self.reader.by_ref().take(len), &mut io::sink())?;
+        self.position += skipped;
+        if skipped < len {
+            return Err(io::ErrorKind::UnexpectedEof.into());
+        }
+        Ok(self.position)
+    }
+}
+
+/// A reader providing a section of another reader as if it was the whole data.
+///
+/// Positions are relative to the start of the section. Archives embedded at some offset of a
+/// larger file, like a tape image, can be opened by [Archive](crate::archive::Archive) this way.
+pub struct OffsetReader<R> {
+    reader: R,
+    /// Position of the section in the underlying reader
+    start: u64,
+    /// Length of the section. `None` extends the section to the end of the underlying reader.
+    len: Option<u64>,
+    /// Position relative to `start`
+    position: u64,
+}
+
+impl<R: Seek> OffsetReader<R> {
+    /// Create a new instance of `OffsetReader` for the section at `start` of `reader`.
+    ///
+    /// The reader is positioned at the start of the section.
+    pub fn new(mut reader: R, start: u64, len: Option<u64>) -> io::Result<Self> {
+        reader.seek(SeekFrom::Start(start))?;
+        Ok(Self {
+            reader,
+            start,
+            len,
+            position: 0,
+        })
+    }
+
+    /// Returns the position of the section in the underlying reader.
+    pub fn start(&self) -> u64 {
+        self.start
+    }
+
+    /// Returns the underlying reader.
+    pub fn into_inner(self) -> R {
+        self.reader
+    }
+}
+
+impl<R: Read> Read for OffsetReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let max = match self.len {
+            Some(len) => buf.len().min(
+                len.saturating_sub(self.position)
+                    .try_into()
+                    .unwrap_or(usize::MAX),
+            ),
+            None => buf.len(),
+        };
+        let read = self.reader.read(&mut buf[..max])?;
+        self.position += read as u64;
+        Ok(read)
+    }
+}
+
+impl<R: Seek> Seek for OffsetReader<R> {
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        let end = match self.len {
+            Some(len) => len,
+            None => self
+                .reader
+                .seek(SeekFrom::End(0))?
+                .saturating_sub(self.start),
+        };
+        let position = match pos {
+            SeekFrom::Start(offset) => Some(offset),
+            SeekFrom::Current(offset) => self.position.checked_add_signed(offset),
+            SeekFrom::End(offset) => end.checked_add_signed(offset),
+        }
+        .filter(|position| position.checked_add(self.start).is_some())
+        .ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "invalid seek to a negative or overflowing position",
+            )
+        })?;
+        self.reader.seek(SeekFrom::Start(self.start + position))?;
+        self.position = position;
+        Ok(position)
+    }
+}
+
+/// Fill `buf` as far as possible. Returns less than its length only at the end of the data.
+pub(crate) fn read_up_to<R: Read>(reader: &mut R, buf: &mut [u8]) -> io::Result<usize> {
+    let mut len = 0;
+    while len < buf.len() {
+        match reader.read(&mut buf[len..]) {
+            Ok(0) => break,
+            Ok(read) => len += read,
+            Err(error) if error.kind() == io::ErrorKind::Interrupted => {}
+            Err(error) => return Err(error),
+        }
+    }
+    Ok(len)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn seeks_forward_by_reading() {
+        let mut reader = ForwardReader::new(b"0123456789".as_slice());
+        let mut buf = [0u8; 2];
+
+        assert_eq!(reader.seek(SeekFrom::Start(3)).unwrap(), 3);
+        reader.read_exact(&mut buf).unwrap();
+        assert_eq!(&buf, b"34");
+        assert_eq!(reader.seek(SeekFrom::Current(2)).unwrap(), 7);
+        assert_eq!(reader.stream_position().unwrap(), 7);
+        reader.read_exact(&mut buf).unwrap();
+        assert_eq!(&buf, b"78");
+    }
+
+    #[test]
+    fn rejects_seeking_backwards() {
+        let mut reader = ForwardReader::new(b"0123456789".as_slice());
+        reader.seek(SeekFrom::Start(5)).unwrap();
+
+        for pos in [SeekFrom::Start(4), SeekFrom::Current(-1), SeekFrom::End(0)] {
+            let error = reader.seek(pos).unwrap_err();
+            assert_eq!(error.kind(), io::ErrorKind::Unsupported);
+        }
+        assert_eq!(reader.stream_position().unwrap(), 5);
+    }
+
+    #[test]
+    fn offset_reader_limits_section() {
+        let mut reader =
+            OffsetReader::new(io::Cursor::new(b"0123456789".to_vec()), 2, Some(5)).unwrap();
+        let mut content = String::new();
+
+        reader.read_to_string(&mut content).unwrap();
+        assert_eq!(content, "23456");
+        assert_eq!(reader.seek(SeekFrom::End(-2)).unwrap(), 3);
+        content.clear();
+        reader.read_to_string(&mut content).unwrap();
+        assert_eq!(content, "56");
+        assert!(reader.seek(SeekFrom::Current(-6)).is_err());
+    }
+
+    #[test]
+    fn offset_reader_extends_to_end() {
+        let mut reader =
+            OffsetReader::new(io::Cursor::new(b"0123456789".to_vec()), 6, None).unwrap();
+        let mut content = String::new();
+
+        assert_eq!(reader.seek(SeekFrom::End(0)).unwrap(), 4);
+        reader.seek(SeekFrom::Start(1)).unwrap();
+        reader.read_to_string(&mut content).unwrap();
+        assert_eq!(content, "789");
+    }
+
+    #[test]
+    fn seeking_beyond_end_fails() {
+        let mut reader = ForwardReader::new(b"0123".as_slice());
+
+        let error = reader.seek(SeekFrom::Start(10)).unwrap_err();
+
+        assert_eq!(error.kind(), io::ErrorKind::UnexpectedEof);
+        assert_eq!(reader.stream_position().unwrap(), 4);
+    }
+}
@@ -0,0 +1,285 @@
+//! Reading several archives stored back to back in one stream, like a tape image
+//!
+//! Tape dumps made with `dd` contain archives one after another. Each archive ends with an end of
+//! archive record and the next archive usually starts at the next tape block.
+
+use std::io::{self, Read, Seek, SeekFrom};
+
+use crate::{
+    Error, Result,
+    archive::{Archive, Record, read_archive_extent, read_file_header},
+    bff::{FILE_MAGIC, FileHeader, OffsetRecord},
+    stream::{OffsetReader, read_up_to},
+};
+
+/// Archives following each other start at a multiple of this size. Tape blocks of 1024 bytes
+/// are covered too.
+pub const TAPE_BLOCK_SIZE: u64 = 512;
+/// Size of the blocks read while searching the next archive.
+const SEARCH_BUFFER_SIZE: usize = 64 * 1024;
+
+/// An archive found in a tape image.
+#[derive(Debug, Clone)]
+pub struct TapeArchive {
+    /// Position of the archive in the tape image
+    pub offset: u64,
+    /// Length of the archive up to the end of its end of archive record without padding
+    pub len: u64,
+    /// Header of the archive
+    pub header: FileHeader,
+    /// Records of the archive. Their file positions are relative to `offset`.
+    pub records: Vec<Record>,
+    /// Offset records of the archive
+    pub offset_records: Vec<OffsetRecord>,
+}
+
+impl TapeArchive {
+    /// Opens the archive in the tape image `reader` to read or extract its files.
+    ///
+    /// The records found while searching the archive are used, so the archive isn't scanned
+    /// again. This equals opening the archive by [Archive::scan].
+    pub fn open<R: Read + Seek>(&self, reader: R) -> Result<Archive<OffsetReader<R>>> {
+        Ok(Archive::from_scanned(
+            OffsetReader::new(reader, self.offset, Some(self.len))?,
+            self.header,
+            self.records.clone(),
+            self.offset_records.clone(),
+        ))
+    }
+}
+
+/// An iterator over all archives in a tape image.
+///
+/// Archives are searched directly behind the end of the previous archive and at every
+/// [TAPE_BLOCK_SIZE] boundary behind it, so any padding between archives is skipped.
+///
+/// An archive without end of archive record extends to the end of the image.
+pub struct TapeArchives<R> {
+    reader: R,
+    /// Position to search the next archive from. `None` if the iteration has ended.
+    next_pos: Option<u64>,
+    /// The image has to start with an archive
+    require_start: bool,
+}
+
+impl<R: Read + Seek> TapeArchives<R> {
+    /// Create a new instance of `TapeArchives` by providing the reader of a tape image.
+    ///
+    /// The first archive has to start at the beginning of the image.
+    pub fn new(reader: R) -> Self {
+        Self {
+            reader,
+            next_pos: Some(0),
+            require_start: true,
+        }
+    }
+
+    /// Create a new instance of `TapeArchives` which searches the first archive too.
+    ///
+    /// This skips leading data which isn't an archive, like the boot image of a mksysb tape.
+    pub fn search(reader: R) -> Self {
+        Self {
+            reader,
+            next_pos: Some(0),
+            require_start: false,
+        }
+    }
+
+    /// Returns the underlying reader.
+    pub fn into_inner(self) -> R {
+        self.reader
+    }
+
+    /// Find the position of the next valid file header at or behind `position`.
+    fn find_header(&mut self, position: u64) -> Result<Option<u64>> {
+        let mut candidate = position;
+        while let Some(found) = find_magic(&mut self.reader, candidate, TAPE_BLOCK_SIZE)? {
+            self.reader.seek(SeekFrom::Start(found))?;
+            match read_file_header(&mut self.reader) {
+                Ok(_) => return Ok(Some(found)),
+                Err(Error::IoError(error)) if error.kind() == io::ErrorKind::UnexpectedEof => {
+                    break;
+                }
+                Err(Error::IoError(error)) => return Err(Error::IoError(error)),
+                Err(_) => {}
+            }
+            candidate = (found + 1).next_multiple_of(TAPE_BLOCK_SIZE);
+        }
+        Ok(None)
+    }
+
+    fn read_archive(&mut self, position: u64) -> Result<Option<TapeArchive>> {
+        // Errors of a header required at the start are reported.
+        let offset = if position == 0 && self.require_start {
+            0
+        } else {
+            match self.find_header(position)? {
+                Some(offset) => offset,
+                None => return Ok(None),
+            }
+        };
+        let mut reader = OffsetReader::new(&mut self.reader, offset, None)?;
+        let extent = read_archive_extent(&mut reader)?;
+        // An empty archive still has to make progress.
+        self.next_pos = Some(offset + extent.len.max(1));
+        Ok(Some(TapeArchive {
+            offset,
+            len: extent.len,
+            header: extent.header,
+            records: extent.records,
+            offset_records: extent.offset_records,
+        }))
+    }
+}
+
+/// Find [FILE_MAGIC] at `position` or at a multiple of `alignment` behind it.
+///
+/// The data is read in large blocks, so long stretches without archive are skipped quickly.
+pub(crate) fn find_magic<R: Read + Seek>(
+    reader: &mut R,
+    position: u64,
+    alignment: u64,
+) -> Result<Option<u64>> {
+    let magic = FILE_MAGIC.to_le_bytes();
+    let mut buf = vec![0u8; SEARCH_BUFFER_SIZE];
+    let mut start = position;
+    loop {
+        reader.seek(SeekFrom::Start(start))?;
+        let len = read_up_to(reader, &mut buf)?;
+        let mut candidate = start;
+        while candidate + magic.len() as u64 <= start + len as u64 {
+            let offset = (candidate - start) as usize;
+            if buf[offset..offset + magic.len()] == magic {
+                return Ok(Some(candidate));
+            }
+            candidate = (candidate + 1).next_multiple_of(alignment);
+        }
+        if len < buf.len() {
+            return Ok(None);
+        }
+        start = candidate;
+    }
+}
+
+impl<R: Read + Seek> Iterator for TapeArchives<R> {
+    type Item = Result<TapeArchive>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let position = self.next_pos.take()?;
+        self.read_archive(position).transpose()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+
+    use super::*;
+    use crate::writer::archive_with_files;
+
+    fn pad_to_block(image: &mut Vec<u8>, block_size: usize) {
+        image.resize(image.len().next_multiple_of(block_size), 0);
+    }
+
+    fn tape_image() -> Vec<u8> {
+        let mut image = include_bytes!("../../resources/test/test.bff").to_vec();
+        image.extend(archive_with_files(&[("./second.txt", b"second archive")]));
+        pad_to_block(&mut image, 512);
+        image.extend(archive_with_files(&[("./third.txt", b"third archive")]));
+        pad_to_block(&mut image, 1024);
+        image
+    }
+
+    #[test]
+    fn finds_all_archives() {
+        let image = tape_image();
+        let archives = TapeArchives::new(Cursor::new(&image))
+            .collect::<Result<Vec<_>>>()
+            .unwrap();
+
+        assert_eq!(archives.len(), 3);
+        assert_eq!(archives[0].offset, 0);
+        assert_eq!(archives[1].offset, 1024);
+        assert_eq!(archives[2].offset % TAPE_BLOCK_SIZE, 0);
+        assert!(archives[2].offset > archives[1].offset + archives[1].len);
+        assert_eq!(
+            archives[1].records[0].filename().to_str(),
+            Some("./second.txt")
+        );
+        assert_eq!(
+            archives[2].records[0].filename().to_str(),
+            Some("./third.txt")
+        );
+    }
+
+    #[test]
+    fn opens_archive_of_image() {
+        let image = tape_image();
+        let mut archives = TapeArchives::new(Cursor::new(&image));
+        let third = archives.nth(2).unwrap().unwrap();
+
+        let mut archive = third.open(archives.into_inner()).unwrap();
+        let mut content = String::new();
+        archive
+            .file("./third.txt")
+            .unwrap()
+            .unwrap()
+            .read_to_string(&mut content)
+            .unwrap();
+
+        assert_eq!(content, "third archive");
+    }
+
+    #[test]
+    fn opens_archive_without_scanning_it_again() {
+        let image = tape_image();
+        let third = TapeArchives::new(Cursor::new(&image))
+            .nth(2)
+            .unwrap()
+            .unwrap();
+
+        // The records are known from the search, so the data isn't parsed again.
+        let archive = third.open(Cursor::new(vec![0u8; image.len()])).unwrap();
+
+        assert_eq!(archive.records().len(), third.records.len());
+        assert_eq!(
+            archive.records()[0].filename().to_str(),
+            Some("./third.txt")
+        );
+        assert_eq!(archive.offset_records().len(), 1);
+    }
+
+    #[test]
+    fn searches_first_archive() {
+        let mut image = vec![0xA5; 70_000];
+        pad_to_block(&mut image, 512);
+        image.extend(archive_with_files(&[("./file.txt", b"content")]));
+        pad_to_block(&mut image, 512);
+        image.extend(archive_with_files(&[("./other.txt", b"other")]));
+
+        let archives = TapeArchives::search(Cursor::new(&image))
+            .collect::<Result<Vec<_>>>()
+            .unwrap();
+
+        assert_eq!(archives.len(), 2);
+        assert_eq!(archives[0].offset, 70_144);
+        assert_eq!(
+            archives[1].records[0].filename().to_str(),
+            Some("./other.txt")
+        );
+    }
+
+    #[test]
+    fn rejects_image_not_starting_with_archive() {
+        let mut image = vec![0; 512];
+        image.extend(archive_with_files(&[("./file.txt", b"content")]));
+
+        let mut archives = TapeArchives::new(Cursor::new(&image));
+
+        assert!(matches!(
+            archives.next(),
+            Some(Err(Error::InvalidFileMagic(0)))
+        ));
+        assert!(archives.next().is_none());
+    }
+}
@@ -1,6 +1,12 @@
+use std::ffi::OsStr;
 use std::fs;
-use std::io::{Error, Result};
-use std::path::Path;
+use std::io::{Error, Read, Result, Seek};
+use std::path::{Path, PathBuf};
+
+/// A reader which is also seekable. Allows using `dyn Read + Seek` trait objects.
+pub trait ReadSeek: Read + Seek {}
+
+impl<T: Read + Seek + ?Sized> ReadSeek for T {}
 
 /// Create a directory and all of its parent directories if needed.
 /// If some part of the path exists but is not a directory, it will be deleted and replaced by the directory.
@@ -13,7 +19,7 @@ pub(crate) fn create_dir_all<P: AsRef<Path>>(path: P) -> Result<()> {
             fs::remove_file(&path)?;
         }
     }
-    Ok(fs::create_dir_all(&path)?)
+    fs::create_dir_all(&path)
 }
 
 /// Create the parent directory of the given path and all of its parent directories if needed.
@@ -26,6 +32,48 @@ pub(crate) fn create_parent_dir_all<D: AsRef<Path>>(destination: &D) -> Result<(
     create_dir_all(parent)
 }
 
+/// Convert the raw bytes of a name stored in an archive to a path.
+///
+/// On Unix the bytes are kept unchanged, so names in legacy code pages land on disk as they were
+/// backed up. Elsewhere all names are decoded as ISO8859-1, which maps every byte to its own
+/// character and thus keeps distinct names distinct. UTF-8 names need [Charset::Utf8].
+///
+/// [Charset::Utf8]: crate::charset::Charset::Utf8
+pub(crate) fn path_from_bytes(bytes: &[u8]) -> PathBuf {
+    #[cfg(unix)]
+    {
+        use std::os::unix::ffi::OsStrExt;
+        PathBuf::from(OsStr::from_bytes(bytes))
+    }
+    #[cfg(not(unix))]
+    {
+        PathBuf::from(bytes.iter().map(|&c| char::from(c)).collect::<String>())
+    }
+}
+
+/// Convert a component of a path to the bytes stored in an archive.
+///
+/// On Unix the bytes are kept unchanged. Elsewhere names are encoded as ISO8859-1, the reverse of
+/// [path_from_bytes]. Characters beyond ISO8859-1 can't be restored by it and are stored as UTF-8.
+pub(crate) fn bytes_from_os_str(name: &OsStr) -> Vec<u8> {
+    #[cfg(unix)]
+    {
+        use std::os::unix::ffi::OsStrExt;
+        name.as_bytes().to_vec()
+    }
+    #[cfg(not(unix))]
+    {
+        let mut bytes = vec![];
+        for c in name.to_string_lossy().chars() {
+            match u8::try_from(c) {
+                Ok(byte) => bytes.push(byte),
+                Err(_) => bytes.extend_from_slice(c.encode_utf8(&mut [0; 4]).as_bytes()),
+            }
+        }
+        bytes
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use fs::File;
@@ -91,4 +139,26 @@ mod tests {
         assert!(file_path.exists());
         assert!(file_path.is_dir());
     }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_path_from_bytes_keeps_bytes() {
+        use std::os::unix::ffi::OsStrExt;
+
+        let path = path_from_bytes(b"backup/caf\xE9");
+
+        assert_eq!(path.as_os_str().as_bytes(), b"backup/caf\xE9");
+        assert_ne!(path, path_from_bytes(b"backup/caf\xE8"));
+    }
+
+    #[cfg(not(unix))]
+    #[test]
+    fn test_path_from_bytes_keeps_names_distinct() {
+        let latin1 = path_from_bytes(b"caf\xE9");
+        let utf8 = path_from_bytes(b"caf\xC3\xA9");
+
+        assert_ne!(latin1, utf8);
+        assert_eq!(bytes_from_os_str(latin1.as_os_str()), b"caf\xE9");
+        assert_eq!(bytes_from_os_str(utf8.as_os_str()), b"caf\xC3\xA9");
+    }
 }
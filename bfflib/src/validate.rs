@@ -0,0 +1,562 @@
+//! Integrity checks of archives
+//!
+//! [Archive::validate](crate::archive::Archive::validate) checks an opened archive beyond
+//! parsing it and returns a [Diagnostic] for every problem found.
+
+use std::{
+    collections::HashSet,
+    fmt::Display,
+    path::{Component, Path, PathBuf},
+};
+
+use normalize_path::NormalizePath;
+
+use crate::{
+    acl::AclData,
+    archive::{Record, RecoveryGap, is_synthetic_acl_record},
+    bff::{FileHeader, OffsetRecord},
+};
+
+/// Severity of a [Diagnostic].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Severity {
+    /// The archive is readable, but unusual
+    Warning,
+    /// Data of the archive is lost or extracting it is unsafe
+    Error,
+}
+
+impl Display for Severity {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Warning => write!(f, "warning"),
+            Self::Error => write!(f, "error"),
+        }
+    }
+}
+
+/// A problem found in an archive.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Issue {
+    /// The archive header has no checksum, so it can't be verified.
+    MissingHeaderChecksum,
+    /// Data between records couldn't be read, see [RecoveryGap].
+    UnreadableData { len: u64 },
+    /// The payload extends beyond the end of the archive.
+    PayloadBeyondEnd { end: u64, archive_len: u64 },
+    /// The payload overlaps the payload of another record.
+    OverlappingPayload { other: PathBuf },
+    /// A compressed payload didn't decode to the size stored in its header.
+    DecodedSizeMismatch { expected: u64, actual: u64 },
+    /// A compressed payload couldn't be decoded.
+    DecodingFailed(String),
+    /// Another record has the same path.
+    DuplicatePath,
+    /// The parent directory of the record isn't part of the archive.
+    MissingParentDirectory(PathBuf),
+    /// The path would escape the extraction directory.
+    EscapingPath,
+    /// A header field observed as 0 in all known archives is set.
+    ReservedFieldSet { field: &'static str, value: u32 },
+    /// The field assumed to hold the upper 32 bits of a size is set. Sizes above 4 GiB aren't
+    /// verified against real archives, so the field may have another meaning.
+    UpperSizeWordSet { field: &'static str, value: u32 },
+    /// The amount of ACL entries doesn't match the length of the ACL payload.
+    AclLengthMismatch { entries: u32, parsed: usize },
+    /// The record follows the end of the archive and may be a stale copy of an earlier record.
+    BehindEndRecord,
+    /// The data ends inside a record header, name or trailer.
+    Truncated,
+    /// The data ends without end of archive record.
+    MissingEndRecord,
+}
+
+impl Issue {
+    /// Returns the severity of the issue.
+    pub fn severity(&self) -> Severity {
+        match self {
+            Self::MissingHeaderChecksum
+            | Self::DuplicatePath
+            | Self::MissingParentDirectory(_)
+            | Self::ReservedFieldSet { .. }
+            | Self::UpperSizeWordSet { .. }
+            | Self::AclLengthMismatch { .. }
+            | Self::BehindEndRecord => Severity::Warning,
+            Self::UnreadableData { .. }
+            | Self::PayloadBeyondEnd { .. }
+            | Self::OverlappingPayload { .. }
+            | Self::DecodedSizeMismatch { .. }
+            | Self::DecodingFailed(_)
+            | Self::EscapingPath
+            | Self::Truncated
+            | Self::MissingEndRecord => Severity::Error,
+        }
+    }
+}
+
+impl Display for Issue {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::MissingHeaderChecksum => write!(f, "Archive header has no checksum."),
+            Self::UnreadableData { len } => write!(f, "{len} bytes of data are unreadable."),
+            Self::PayloadBeyondEnd { end, archive_len } => write!(
+                f,
+                "Payload ends at {end}, behind the end of the archive at {archive_len}."
+            ),
+            Self::OverlappingPayload { other } => {
+                write!(f, "Payload overlaps payload of {}.", other.display())
+            }
+            Self::DecodedSizeMismatch { expected, actual } => write!(
+                f,
+                "Payload decoded to {actual} bytes, but {expected} bytes were expected."
+            ),
+            Self::DecodingFailed(error) => write!(f, "Payload couldn't be decoded: {error}"),
+            Self::DuplicatePath => write!(f, "Path is stored more than once."),
+            Self::MissingParentDirectory(parent) => {
+                write!(f, "Parent directory {} is missing.", parent.display())
+            }
+            Self::EscapingPath => write!(f, "Path escapes the extraction directory."),
+            Self::ReservedFieldSet { field, value } => {
+                write!(f, "Reserved field {field} is set to {value:#x}.")
+            }
+            Self::UpperSizeWordSet { field, value } => write!(
+                f,
+                "Upper size word {field} is set to {value:#x}, sizes above 4 GiB are unverified."
+            ),
+            Self::AclLengthMismatch { entries, parsed } => write!(
+                f,
+                "ACL announces {entries} entries, but its payload holds {parsed}."
+            ),
+            Self::BehindEndRecord => write!(
+                f,
+                "Record follows the end of the archive and may be a stale copy."
+            ),
+            Self::Truncated => write!(f, "Record is cut off by the end of the data."),
+            Self::MissingEndRecord => write!(
+                f,
+                "Data ends without end of archive record, the archive may be truncated."
+            ),
+        }
+    }
+}
+
+/// A problem found in an archive and where it was found.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Diagnostic {
+    /// Position in the archive
+    pub offset: u64,
+    /// Path of the affected record. `None` for problems of the archive itself.
+    pub path: Option<PathBuf>,
+    pub issue: Issue,
+}
+
+impl Diagnostic {
+    /// Returns the severity of the issue.
+    pub fn severity(&self) -> Severity {
+        self.issue.severity()
+    }
+
+    /// Returns `true` if the issue is an error.
+    pub fn is_error(&self) -> bool {
+        self.severity() == Severity::Error
+    }
+
+    fn record(offset: u64, record: &Record, issue: Issue) -> Self {
+        Self {
+            offset,
+            path: Some(record.filename().to_path_buf()),
+            issue,
+        }
+    }
+}
+
+impl Display for Diagnostic {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{} at offset {}: ", self.severity(), self.offset)?;
+        if let Some(path) = &self.path {
+            write!(f, "{}: ", path.display())?;
+        }
+        write!(f, "{}", self.issue)
+    }
+}
+
+/// Check the archive header.
+pub(crate) fn check_header(header: &FileHeader) -> Vec<Diagnostic> {
+    let mut diagnostics = vec![];
+    if header.stored_checksum() == 0 {
+        diagnostics.push(Diagnostic {
+            offset: 0,
+            path: None,
+            issue: Issue::MissingHeaderChecksum,
+        });
+    }
+    for (field, value, offset) in [
+        ("unk1_c", header.unk1_c, 28),
+        ("unk20", header.unk20, 32),
+        ("unk30", header.unk30, 48),
+        ("unk3_c", header.unk3_c, 60),
+        ("unk40", header.unk40, 64),
+    ] {
+        if value != 0 {
+            diagnostics.push(Diagnostic {
+                offset,
+                path: None,
+                issue: Issue::ReservedFieldSet { field, value },
+            });
+        }
+    }
+    diagnostics
+}
+
+/// Turn data skipped in recovery mode into diagnostics.
+pub(crate) fn check_gaps(gaps: &[RecoveryGap]) -> Vec<Diagnostic> {
+    gaps.iter()
+        .map(|gap| Diagnostic {
+            offset: gap.start,
+            path: None,
+            issue: Issue::UnreadableData {
+                len: gap.end - gap.start,
+            },
+        })
+        .collect()
+}
+
+/// Check the records of an archive of `archive_len` bytes without reading their payloads.
+pub(crate) fn check_records(records: &[Record], archive_len: u64) -> Vec<Diagnostic> {
+    let mut diagnostics = vec![];
+    let directories = records
+        .iter()
+        .filter(|record| record.mode().file_type().is_some_and(|t| t.is_directory()))
+        .map(|record| normalized(record.filename()))
+        .collect::<HashSet<_>>();
+    let mut paths = HashSet::new();
+    let mut previous_payload: Option<(&Record, u64)> = None;
+
+    for record in records {
+        let offset = record.header_position();
+        let path = normalized(record.filename());
+
+        if record.filename().components().any(|c| {
+            matches!(
+                c,
+                Component::Prefix(_) | Component::RootDir | Component::ParentDir
+            )
+        }) {
+            diagnostics.push(Diagnostic::record(offset, record, Issue::EscapingPath));
+        }
+        // ACL texts are stored under the path of the record they belong to.
+        if !is_synthetic_acl_record(record) && !paths.insert(path.clone()) {
+            diagnostics.push(Diagnostic::record(offset, record, Issue::DuplicatePath));
+        }
+        if let Some(parent) = path.parent()
+            && !parent.as_os_str().is_empty()
+            && !directories.contains(parent)
+        {
+            diagnostics.push(Diagnostic::record(
+                offset,
+                record,
+                Issue::MissingParentDirectory(parent.to_path_buf()),
+            ));
+        }
+
+        let reserved = record.header().unk34;
+        if reserved != 0 {
+            diagnostics.push(Diagnostic::record(
+                offset,
+                record,
+                Issue::ReservedFieldSet {
+                    field: "unk34",
+                    value: reserved,
+                },
+            ));
+        }
+        let header = record.header();
+        for (field, value) in [("unk30", header.unk30), ("unk3_c", header.unk3_c)] {
+            if value != 0 {
+                diagnostics.push(Diagnostic::record(
+                    offset,
+                    record,
+                    Issue::UpperSizeWordSet { field, value },
+                ));
+            }
+        }
+
+        if let Some((entries, parsed)) = acl_entry_counts(record)
+            && entries as usize != parsed
+        {
+            diagnostics.push(Diagnostic::record(
+                offset,
+                record,
+                Issue::AclLengthMismatch { entries, parsed },
+            ));
+        }
+
+        if record.size() == 0 || record.compressed_size() == 0 {
+            continue;
+        }
+        let start = record.file_position();
+        let end = start.saturating_add(record.compressed_size());
+        if end > archive_len {
+            diagnostics.push(Diagnostic::record(
+                start,
+                record,
+                Issue::PayloadBeyondEnd { end, archive_len },
+            ));
+        }
+        if let Some((other, other_end)) = previous_payload
+            && start < other_end
+        {
+            diagnostics.push(Diagnostic::record(
+                start,
+                record,
+                Issue::OverlappingPayload {
+                    other: other.filename().to_path_buf(),
+                },
+            ));
+        }
+        if previous_payload.is_none_or(|(_, other_end)| end > other_end) {
+            previous_payload = Some((record, end));
+        }
+    }
+    diagnostics
+}
+
+/// Check that the archive of `archive_len` bytes ends with an [OffsetRecord] and isn't cut off
+/// inside the record at `truncated`.
+///
+/// Records following the first offset record, which only recovery mode reads, are reported too.
+pub(crate) fn check_end(
+    records: &[Record],
+    offset_records: &[OffsetRecord],
+    truncated: Option<u64>,
+    archive_len: u64,
+) -> Vec<Diagnostic> {
+    let mut diagnostics = vec![];
+    if let Some(offset) = truncated {
+        diagnostics.push(Diagnostic {
+            offset,
+            path: None,
+            issue: Issue::Truncated,
+        });
+    }
+    let Some(end) = offset_records.first() else {
+        diagnostics.push(Diagnostic {
+            offset: archive_len,
+            path: None,
+            issue: Issue::MissingEndRecord,
+        });
+        return diagnostics;
+    };
+    diagnostics.extend(
+        records
+            .iter()
+            .filter(|record| record.header_position() > end.position)
+            .map(|record| {
+                Diagnostic::record(record.header_position(), record, Issue::BehindEndRecord)
+            }),
+    );
+    diagnostics
+}
+
+/// Returns the amount of ACL entries announced and found in the ACL payload of the record.
+///
+/// The base entries of AIXC ACLs are stored in a fixed location and not counted.
+fn acl_entry_counts(record: &Record) -> Option<(u32, usize)> {
+    let metadata_len = record.record_acl().acl_len;
+    if metadata_len == 0 {
+        return None;
+    }
+    match record.acl()? {
+        AclData::Aixc(acl) => Some((
+            acl.metadata.num_entries.saturating_sub(3),
+            acl.entries.len(),
+        )),
+        // The entries of some NFS4 ACLs are stored as text in a separate record.
+        AclData::Nfs4(acl) if acl.text.is_some() && acl.entries.is_empty() => None,
+        AclData::Nfs4(acl) => Some((acl.metadata.num_entries, acl.entries.len())),
+    }
+}
+
+/// Returns the path without leading `./` and other redundant components.
+fn normalized(path: &Path) -> PathBuf {
+    path.normalize()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+
+    use super::*;
+    use crate::{
+        archive::{Archive, read_file_header},
+        writer::archive_with_files,
+    };
+
+    fn issues(data: Vec<u8>) -> Vec<Issue> {
+        let mut archive = Archive::new(Cursor::new(data)).unwrap();
+        archive
+            .validate()
+            .unwrap()
+            .into_iter()
+            .map(|diagnostic| diagnostic.issue)
+            .collect()
+    }
+
+    #[test]
+    fn reports_duplicate_paths_and_missing_parents() {
+        let data = archive_with_files(&[
+            ("./file.txt", b"first"),
+            ("./dir/file.txt", b"second"),
+            ("file.txt", b"third"),
+        ]);
+
+        assert_eq!(
+            issues(data),
+            [
+                Issue::MissingParentDirectory(PathBuf::from("dir")),
+                Issue::DuplicatePath
+            ]
+        );
+    }
+
+    #[test]
+    fn reports_escaping_path() {
+        let mut data = archive_with_files(&[("./aa/file.txt", b"content")]);
+        let name = data.windows(2).position(|window| window == b"aa").unwrap();
+        data[name..name + 2].copy_from_slice(b"..");
+
+        assert!(issues(data).contains(&Issue::EscapingPath));
+    }
+
+    #[test]
+    fn reports_reserved_field() {
+        let mut data = archive_with_files(&[("./file.txt", b"content")]);
+        // `unk34` of the first record, which follows the 72 byte file header
+        data[124..128].copy_from_slice(&7u32.to_le_bytes());
+
+        assert_eq!(
+            issues(data),
+            [Issue::ReservedFieldSet {
+                field: "unk34",
+                value: 7
+            }]
+        );
+    }
+
+    #[test]
+    fn reports_upper_size_words() {
+        let mut data = archive_with_files(&[("./file.txt", b"content")]);
+        // `unk30` of the first record
+        data[120..124].copy_from_slice(&1u32.to_le_bytes());
+
+        assert_eq!(
+            issues(data),
+            [Issue::UpperSizeWordSet {
+                field: "unk30",
+                value: 1
+            }]
+        );
+    }
+
+    #[test]
+    fn reports_reserved_header_fields() {
+        let data = archive_with_files(&[("./file.txt", b"content")]);
+        let mut header = read_file_header(&mut data.as_slice()).unwrap();
+        assert!(check_header(&header).is_empty());
+
+        header.unk20 = 3;
+        header.unk40 = 5;
+
+        assert_eq!(
+            check_header(&header),
+            [
+                Diagnostic {
+                    offset: 32,
+                    path: None,
+                    issue: Issue::ReservedFieldSet {
+                        field: "unk20",
+                        value: 3
+                    }
+                },
+                Diagnostic {
+                    offset: 64,
+                    path: None,
+                    issue: Issue::ReservedFieldSet {
+                        field: "unk40",
+                        value: 5
+                    }
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn reports_records_behind_end_record() {
+        let mut data =
+            archive_with_files(&[("./first.txt", b"first"), ("./second.txt", b"second")]);
+        let second = data
+            .windows(12)
+            .position(|window| window == b"./second.txt")
+            .unwrap()
+            - std::mem::size_of::<crate::bff::RecordHeader>();
+        let end_record = data[data.len() - 8..].to_vec();
+        data.splice(second..second, end_record);
+
+        let mut archive = Archive::recover(Cursor::new(data)).unwrap();
+        let diagnostics = archive.validate().unwrap();
+
+        assert_eq!(
+            diagnostics,
+            [Diagnostic {
+                offset: second as u64 + 8,
+                path: Some(PathBuf::from("./second.txt")),
+                issue: Issue::BehindEndRecord
+            }]
+        );
+    }
+
+    #[test]
+    fn reports_truncated_archives() {
+        let data = include_bytes!("../../resources/test/test.bff");
+        // Inside the first record, a name, a trailer, a payload and the end record
+        for len in [72, 80, 150, 200, 300, 500, 588] {
+            for archive in [
+                Archive::scan(Cursor::new(&data[..len])),
+                Archive::recover(Cursor::new(&data[..len])),
+            ] {
+                let diagnostics = archive.unwrap().validate().unwrap();
+
+                assert!(
+                    diagnostics.iter().any(Diagnostic::is_error),
+                    "{len} bytes: {diagnostics:?}"
+                );
+            }
+        }
+        let mut archive = Archive::recover(Cursor::new(&data[..300])).unwrap();
+        let issues = archive
+            .validate()
+            .unwrap()
+            .into_iter()
+            .map(|diagnostic| diagnostic.issue)
+            .collect::<Vec<_>>();
+        assert!(issues.contains(&Issue::Truncated));
+        assert!(issues.contains(&Issue::MissingEndRecord));
+
+        let mut archive = Archive::recover(Cursor::new(data)).unwrap();
+        assert!(archive.validate().unwrap().is_empty());
+    }
+
+    #[test]
+    fn formats_diagnostic() {
+        let diagnostic = Diagnostic {
+            offset: 72,
+            path: Some(PathBuf::from("./file.txt")),
+            issue: Issue::DuplicatePath,
+        };
+
+        assert_eq!(
+            diagnostic.to_string(),
+            "warning at offset 72: ./file.txt: Path is stored more than once."
+        );
+    }
+}
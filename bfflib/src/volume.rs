@@ -0,0 +1,337 @@
+//! Reading archives split across several volumes
+//!
+//! AIX `backup` can split an archive into several volumes. Every volume starts with its own
+//! [FileHeader] carrying the volume number, the records simply continue behind it. A record and
+//! its payload may cross the end of a volume.
+
+use std::io::{self, Read, Seek, SeekFrom};
+
+use crate::{Error, Result, archive::read_file_header, bff::FileHeader};
+
+/// A reader joining the volumes of an archive into a single stream.
+///
+/// The stream starts with the [FileHeader] of the first volume followed by the data of all
+/// volumes without their headers. It can be passed to [Archive](crate::archive::Archive) or
+/// [RecordIter](crate::archive::RecordIter) like a single volume archive.
+pub struct VolumeReader<R> {
+    volumes: Vec<Volume<R>>,
+    /// Headers of all volumes
+    headers: Vec<FileHeader>,
+    /// Position in the joined stream
+    position: u64,
+    /// Index of the volume containing `position`
+    current: usize,
+    /// The reader of the current volume has to be positioned before reading
+    seek_pending: bool,
+}
+
+/// A single volume and the part of the joined stream it provides.
+struct Volume<R> {
+    reader: R,
+    /// Position of the volume data in the joined stream
+    start: u64,
+    /// Position of the data behind the volume header
+    data_start: u64,
+    /// Amount of data bytes in the volume
+    len: u64,
+}
+
+impl<R: Read + Seek> VolumeReader<R> {
+    /// Create a new instance of `VolumeReader` from the volumes in order.
+    ///
+    /// The header of every volume is read and validated. All volumes have to be written by the
+    /// same backup run, so their starting dates have to match. Otherwise
+    /// [Error::VolumeMismatch] is returned. Several volumes have to be given in the order of their
+    /// [volume numbers](FileHeader::volume_number), otherwise [Error::VolumeOutOfOrder] is
+    /// returned.
+    pub fn new(readers: Vec<R>) -> Result<Self> {
+        if readers.is_empty() {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, "No volumes given.").into());
+        }
+        let mut volumes = Vec::with_capacity(readers.len());
+        let mut headers = Vec::with_capacity(readers.len());
+        let mut start = 0;
+        let single = readers.len() == 1;
+        for (index, mut reader) in readers.into_iter().enumerate() {
+            reader.rewind()?;
+            let header = read_file_header(&mut reader)?;
+            if let Some(first) = headers.first()
+                && !same_backup(first, &header)
+            {
+                return Err(Error::VolumeMismatch(index + 1));
+            }
+            // Joined in the wrong order, the records would continue at the wrong data.
+            if !single && usize::from(header.volume_number()) != index + 1 {
+                return Err(Error::VolumeOutOfOrder {
+                    position: index + 1,
+                    number: header.volume_number(),
+                });
+            }
+            // The joined stream keeps the header of the first volume only.
+            let data_start = if index == 0 {
+                0
+            } else {
+                reader.stream_position()?
+            };
+            let len = reader.seek(SeekFrom::End(0))? - data_start;
+            volumes.push(Volume {
+                reader,
+                start,
+                data_start,
+                len,
+            });
+            headers.push(header);
+            start += len;
+        }
+        Ok(Self {
+            volumes,
+            headers,
+            position: 0,
+            current: 0,
+            seek_pending: true,
+        })
+    }
+}
+
+impl<R> VolumeReader<R> {
+    /// Returns the [FileHeader]s of all volumes in order.
+    pub fn headers(&self) -> &[FileHeader] {
+        &self.headers
+    }
+
+    /// Returns the amount of volumes.
+    pub fn len(&self) -> usize {
+        self.volumes.len()
+    }
+
+    /// Returns `true` if there are no volumes. This never applies to a successfully created reader.
+    pub fn is_empty(&self) -> bool {
+        self.volumes.is_empty()
+    }
+
+    /// Returns the underlying volume readers.
+    pub fn into_inner(self) -> Vec<R> {
+        self.volumes
+            .into_iter()
+            .map(|volume| volume.reader)
+            .collect()
+    }
+
+    /// Returns the length of the joined stream.
+    fn total_len(&self) -> u64 {
+        self.volumes
+            .last()
+            .map_or(0, |volume| volume.start + volume.len)
+    }
+}
+
+/// Returns `true` if both volume headers were written by the same backup run.
+fn same_backup(first: &FileHeader, other: &FileHeader) -> bool {
+    let (first_date, other_date) = (first.starting_date, other.starting_date);
+    first_date == other_date
+}
+
+impl<R: Read + Seek> Read for VolumeReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        loop {
+            let last = self.volumes.len() - 1;
+            let volume = &mut self.volumes[self.current];
+            let offset = self.position - volume.start;
+            if offset >= volume.len {
+                if self.current == last {
+                    return Ok(0);
+                }
+                self.current += 1;
+                self.seek_pending = true;
+                continue;
+            }
+            if self.seek_pending {
+                volume
+                    .reader
+                    .seek(SeekFrom::Start(volume.data_start + offset))?;
+                self.seek_pending = false;
+            }
+            let len = buf.len().min((volume.len - offset) as usize);
+            let read = volume.reader.read(&mut buf[..len])?;
+            if read == 0 && len > 0 {
+                // The volume got shorter since its length was determined.
+                return Err(io::ErrorKind::UnexpectedEof.into());
+            }
+            self.position += read as u64;
+            return Ok(read);
+        }
+    }
+}
+
+impl<R: Read + Seek> Seek for VolumeReader<R> {
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        let position = match pos {
+            SeekFrom::Start(offset) => Some(offset),
+            SeekFrom::Current(offset) => self.position.checked_add_signed(offset),
+            SeekFrom::End(offset) => self.total_len().checked_add_signed(offset),
+        }
+        .ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "invalid seek to a negative or overflowing position",
+            )
+        })?;
+        if position != self.position {
+            self.position = position;
+            // Positions behind the end belong to the last volume.
+            self.current = self
+                .volumes
+                .partition_point(|volume| volume.start + volume.len <= position)
+                .min(self.volumes.len() - 1);
+            self.seek_pending = true;
+        }
+        Ok(self.position)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+
+    use super::*;
+    use crate::{archive::Archive, bff::compute_file_header_checksum, writer::archive_with_files};
+
+    const HEADER_LEN: usize = std::mem::size_of::<FileHeader>();
+
+    fn sample_archive() -> Vec<u8> {
+        archive_with_files(&[
+            ("./first.txt", b"first file"),
+            ("./second.txt", &b"0123456789".repeat(20)),
+            ("./third.txt", b"third file"),
+        ])
+    }
+
+    /// Returns the archive header of `archive` numbered as volume `number`.
+    fn volume_header(archive: &[u8], number: u16) -> Vec<u8> {
+        let mut header = archive[..HEADER_LEN].to_vec();
+        header[4..6].fill(0);
+        header[6..8].copy_from_slice(&number.to_le_bytes());
+        let checksum = compute_file_header_checksum(&header).unwrap();
+        header[4..6].copy_from_slice(&checksum.to_le_bytes());
+        header
+    }
+
+    /// Split `archive` at the given positions into volumes, each starting with the archive header.
+    fn split_volumes(archive: &[u8], splits: &[usize]) -> Vec<Cursor<Vec<u8>>> {
+        let mut volumes = vec![];
+        let mut start = 0;
+        for &end in splits.iter().chain([archive.len()].iter()) {
+            let mut volume = if start == 0 {
+                vec![]
+            } else {
+                volume_header(archive, volumes.len() as u16 + 1)
+            };
+            volume.extend_from_slice(&archive[start..end]);
+            volumes.push(Cursor::new(volume));
+            start = end;
+        }
+        volumes
+    }
+
+    fn read_file<R: Read + Seek>(archive: &mut Archive<R>, filename: &str) -> Vec<u8> {
+        let mut content = vec![];
+        archive
+            .file(filename)
+            .unwrap()
+            .unwrap()
+            .read_to_end(&mut content)
+            .unwrap();
+        content
+    }
+
+    #[test]
+    fn joins_volumes() {
+        let archive = sample_archive();
+        let mut reader = VolumeReader::new(split_volumes(&archive, &[100, 200])).unwrap();
+        let mut joined = vec![];
+        reader.read_to_end(&mut joined).unwrap();
+
+        assert_eq!(reader.len(), 3);
+        assert_eq!(reader.headers().len(), 3);
+        assert_eq!(joined, archive);
+    }
+
+    #[test]
+    fn seeks_across_volumes() {
+        let archive = sample_archive();
+        let mut reader = VolumeReader::new(split_volumes(&archive, &[100, 200])).unwrap();
+        let mut buf = [0u8; 20];
+
+        reader.seek(SeekFrom::Start(190)).unwrap();
+        reader.read_exact(&mut buf).unwrap();
+        assert_eq!(buf, archive[190..210]);
+
+        reader.seek(SeekFrom::Current(-110)).unwrap();
+        reader.read_exact(&mut buf).unwrap();
+        assert_eq!(buf, archive[100..120]);
+
+        assert_eq!(reader.seek(SeekFrom::End(0)).unwrap(), archive.len() as u64);
+        assert_eq!(reader.read(&mut buf).unwrap(), 0);
+    }
+
+    #[test]
+    fn reads_payload_crossing_volumes() {
+        let archive = sample_archive();
+        let start = archive
+            .windows(10)
+            .position(|window| window == b"0123456789")
+            .unwrap();
+        let mut archive = Archive::open_volumes(split_volumes(&archive, &[start + 55])).unwrap();
+
+        assert_eq!(archive.records().len(), 3);
+        assert_eq!(
+            read_file(&mut archive, "./second.txt"),
+            b"0123456789".repeat(20)
+        );
+        assert_eq!(read_file(&mut archive, "./third.txt"), b"third file");
+    }
+
+    #[test]
+    fn rejects_volume_of_other_backup() {
+        let archive = sample_archive();
+        let mut volumes = split_volumes(&archive, &[200]);
+        let volume = volumes[1].get_mut();
+        // Change the starting date and drop the checksum, which would not match anymore.
+        volume[12] ^= 1;
+        volume[4..6].fill(0);
+
+        assert!(matches!(
+            VolumeReader::new(volumes),
+            Err(Error::VolumeMismatch(2))
+        ));
+    }
+
+    #[test]
+    fn rejects_swapped_volumes() {
+        let archive = sample_archive();
+        let mut volumes = split_volumes(&archive, &[100, 200]);
+        volumes.swap(1, 2);
+
+        assert!(matches!(
+            VolumeReader::new(volumes),
+            Err(Error::VolumeOutOfOrder {
+                position: 2,
+                number: 3
+            })
+        ));
+    }
+
+    #[test]
+    fn rejects_volume_without_header() {
+        let archive = sample_archive();
+        let volumes = vec![
+            Cursor::new(archive[..200].to_vec()),
+            Cursor::new(archive[200..].to_vec()),
+        ];
+
+        assert!(matches!(
+            VolumeReader::new(volumes),
+            Err(Error::InvalidFileMagic(_))
+        ));
+    }
+}
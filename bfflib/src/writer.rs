@@ -0,0 +1,604 @@
+//! Writing an BFF archive
+
+use std::{
+    fs::{self, Metadata},
+    io::{self, Read, Write},
+    path::{Component, Path},
+};
+
+use chrono::Utc;
+
+use crate::{
+    Error, Result,
+    acl::RecordAcl,
+    bff::{FILE_MAGIC, FileHeader, HUFFMAN_MAGIC, RecordHeader, compute_file_header_checksum},
+    huffman::HuffmanEncoder,
+    util::bytes_from_os_str,
+};
+
+/// Magic number of raw (not compressed) records.
+const RAW_MAGIC: u16 = 0xEA6B;
+/// Record format marker of regular entries.
+const RECORD_FORMAT: u8 = 0x0B;
+/// Record format marker of the record terminating an archive.
+const END_FORMAT: u8 = 0x07;
+/// File type bits of a directory.
+const S_IFDIR: u32 = 0o040000;
+/// File type bits of a regular file.
+const S_IFREG: u32 = 0o100000;
+/// File type bits of a symbolic link.
+const S_IFLNK: u32 = 0o120000;
+
+/// Metadata of a record written by [ArchiveWriter].
+#[derive(Clone, Debug)]
+pub struct EntryMetadata {
+    /// Permission bits (rwx...). The file type bits are set by the writer.
+    pub mode: u32,
+    /// User ID number of the file
+    pub uid: u32,
+    /// Group ID number of the file
+    pub gid: u32,
+    /// Last access timestamp of the file
+    pub atime: u32,
+    /// Last modified timestamp of the file
+    pub mtime: u32,
+    /// Inode status-change timestamp (`ctime`)
+    pub ctime: u32,
+}
+
+impl Default for EntryMetadata {
+    fn default() -> Self {
+        let now = Utc::now().timestamp() as u32;
+        Self {
+            mode: 0o644,
+            uid: 0,
+            gid: 0,
+            atime: now,
+            mtime: now,
+            ctime: now,
+        }
+    }
+}
+
+impl From<&Metadata> for EntryMetadata {
+    fn from(metadata: &Metadata) -> Self {
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::MetadataExt;
+            Self {
+                mode: metadata.mode() & 0o7777,
+                uid: metadata.uid(),
+                gid: metadata.gid(),
+                atime: metadata.atime() as u32,
+                mtime: metadata.mtime() as u32,
+                ctime: metadata.ctime() as u32,
+            }
+        }
+        #[cfg(not(unix))]
+        {
+            let timestamp = |time: std::io::Result<std::time::SystemTime>| {
+                time.ok()
+                    .and_then(|time| time.duration_since(std::time::UNIX_EPOCH).ok())
+                    .map(|duration| duration.as_secs() as u32)
+                    .unwrap_or_default()
+            };
+            let mtime = timestamp(metadata.modified());
+            Self {
+                mode: if metadata.is_dir() { 0o755 } else { 0o644 },
+                uid: 0,
+                gid: 0,
+                atime: timestamp(metadata.accessed()),
+                mtime,
+                ctime: mtime,
+            }
+        }
+    }
+}
+
+/// Writer creating BFF archives in the "by name" format.
+///
+/// The produced archives can be read by [crate::archive::Archive].
+pub struct ArchiveWriter<W: Write> {
+    writer: W,
+    /// Identifier of the next directory record. Directories count down from `0xFFFF_FFFF`.
+    next_directory_id: u32,
+}
+
+impl<W: Write> ArchiveWriter<W> {
+    /// Creates a new ArchiveWriter and writes a "by name" [FileHeader] dated now.
+    pub fn new(writer: W) -> Result<Self> {
+        let now = Utc::now().timestamp() as u32;
+        let header = FileHeader {
+            magic: FILE_MAGIC,
+            // Sampled AIX archives carry 1 in the upper half of the checksum word.
+            checksum: 0x0001_0000,
+            current_date: now,
+            starting_date: now,
+            unk10: 0x7FFF_FFFF,
+            disk_name: *b"by name\0",
+            unk1_c: 0,
+            unk20: 0,
+            filesystem_name: *b"by name\0",
+            unk2_c: 0,
+            unk30: 0,
+            username: *b"root\0\0\0\0",
+            unk3_c: 0,
+            unk40: 0,
+            unk44: 100,
+        };
+        Self::with_header(writer, header)
+    }
+
+    /// Creates a new ArchiveWriter using a custom [FileHeader].
+    ///
+    /// The magic number and the checksum of the header are set by the writer.
+    pub fn with_header(mut writer: W, mut header: FileHeader) -> Result<Self> {
+        header.magic = FILE_MAGIC;
+        header.checksum &= 0xFFFF_0000;
+        let mut bytes = struct_bytes(&header);
+        let checksum =
+            compute_file_header_checksum(&bytes).ok_or(Error::InvalidFileChecksumFormat)?;
+        bytes[4..6].copy_from_slice(&checksum.to_le_bytes());
+        writer.write_all(&bytes)?;
+        Ok(Self {
+            writer,
+            next_directory_id: u32::MAX,
+        })
+    }
+
+    /// Appends a directory record.
+    pub fn append_directory<P: AsRef<Path>>(
+        &mut self,
+        name: P,
+        metadata: &EntryMetadata,
+    ) -> Result<()> {
+        let directory_id = self.next_directory_id;
+        self.next_directory_id = self.next_directory_id.wrapping_sub(1);
+        let header = RecordHeader {
+            unk08: directory_id,
+            mode: S_IFDIR | (metadata.mode & 0o7777),
+            ..record_header(metadata)
+        };
+        self.write_record(header, name.as_ref(), None, &[], RAW_MAGIC)
+    }
+
+    /// Appends a regular file record containing `data`.
+    pub fn append_file<P: AsRef<Path>>(
+        &mut self,
+        name: P,
+        metadata: &EntryMetadata,
+        data: &[u8],
+    ) -> Result<()> {
+        self.append_reader(name, metadata, data.len() as u64, data)
+    }
+
+    /// Appends a regular file record containing `size` bytes read from `reader`.
+    ///
+    /// The content is streamed into the archive without holding it in memory. If `reader` ends
+    /// before `size` bytes, an [io::ErrorKind::UnexpectedEof] error is returned and the archive
+    /// is incomplete. Files bigger than 4 GiB return [Error::FileToBig], see
+    /// [ArchiveWriter::append_file_compressed].
+    pub fn append_reader<P: AsRef<Path>, R: Read>(
+        &mut self,
+        name: P,
+        metadata: &EntryMetadata,
+        size: u64,
+        reader: R,
+    ) -> Result<()> {
+        if size > u64::from(u32::MAX) {
+            return Err(Error::FileToBig);
+        }
+        let mut header = RecordHeader {
+            mode: S_IFREG | (metadata.mode & 0o7777),
+            ..record_header(metadata)
+        };
+        header.set_file_size(size);
+        header.set_payload_size(size);
+        self.write_record_head(header, name.as_ref(), None, RAW_MAGIC)?;
+        let copied = io::copy(&mut reader.take(size), &mut self.writer)?;
+        if copied != size {
+            return Err(io::Error::new(
+                io::ErrorKind::UnexpectedEof,
+                format!("The file ended after {copied} of {size} bytes."),
+            )
+            .into());
+        }
+        self.write_padding(size)
+    }
+
+    /// Appends a regular file record containing `data` Huffman encoded.
+    ///
+    /// Data bigger than 4 GiB returns [Error::FileToBig]. The fields assumed to hold the upper
+    /// 32 bits of large sizes aren't verified, so AIX may not restore such records.
+    pub fn append_file_compressed<P: AsRef<Path>>(
+        &mut self,
+        name: P,
+        metadata: &EntryMetadata,
+        data: &[u8],
+    ) -> Result<()> {
+        if data.len() as u64 > u64::from(u32::MAX) {
+            return Err(Error::FileToBig);
+        }
+        let mut encoder = HuffmanEncoder::new(Vec::new());
+        encoder.write_all(data)?;
+        let encoded = encoder.finish()?;
+        let mut header = RecordHeader {
+            mode: S_IFREG | (metadata.mode & 0o7777),
+            ..record_header(metadata)
+        };
+        header.set_file_size(data.len() as u64);
+        header.set_payload_size(encoded.len() as u64);
+        self.write_record(header, name.as_ref(), None, &encoded, HUFFMAN_MAGIC)
+    }
+
+    /// Appends a symbolic link record pointing to `target`.
+    pub fn append_symlink<P: AsRef<Path>, T: AsRef<Path>>(
+        &mut self,
+        name: P,
+        target: T,
+        metadata: &EntryMetadata,
+    ) -> Result<()> {
+        let header = RecordHeader {
+            mode: S_IFLNK | (metadata.mode & 0o7777),
+            ..record_header(metadata)
+        };
+        self.write_record(header, name.as_ref(), Some(target.as_ref()), &[], RAW_MAGIC)
+    }
+
+    /// Appends a file system entry at `path` as record `name`.
+    ///
+    /// Directories are added without their content. Use [ArchiveWriter::append_dir_all] to add a whole tree.
+    pub fn append_path<P: AsRef<Path>, Q: AsRef<Path>>(&mut self, path: P, name: Q) -> Result<()> {
+        let path = path.as_ref();
+        let metadata = fs::symlink_metadata(path)?;
+        let entry_metadata = EntryMetadata::from(&metadata);
+        let file_type = metadata.file_type();
+        if file_type.is_dir() {
+            self.append_directory(name, &entry_metadata)
+        } else if file_type.is_file() {
+            let file = fs::File::open(path)?;
+            self.append_reader(name, &entry_metadata, metadata.len(), file)
+        } else if file_type.is_symlink() {
+            let target = fs::read_link(path)?;
+            self.append_symlink(name, target, &entry_metadata)
+        } else {
+            Err(Error::UnsupportedFileType(format!("{file_type:?}")))
+        }
+    }
+
+    /// Appends the directory `path` and all of its content recursively below the record name `name`.
+    ///
+    /// Entries are written in sorted order with each directory preceding its content.
+    pub fn append_dir_all<P: AsRef<Path>, Q: AsRef<Path>>(
+        &mut self,
+        path: P,
+        name: Q,
+    ) -> Result<()> {
+        let path = path.as_ref();
+        let name = name.as_ref();
+        self.append_path(path, name)?;
+        if !fs::symlink_metadata(path)?.is_dir() {
+            return Ok(());
+        }
+
+        let mut entries = fs::read_dir(path)?.collect::<std::io::Result<Vec<_>>>()?;
+        entries.sort_by_key(|entry| entry.file_name());
+        for entry in entries {
+            self.append_dir_all(entry.path(), name.join(entry.file_name()))?;
+        }
+        Ok(())
+    }
+
+    /// Writes the end of archive record and returns the underlying writer.
+    pub fn finish(mut self) -> Result<W> {
+        let mut block = [0u8; 8];
+        block[0] = 1;
+        block[1] = END_FORMAT;
+        block[2..4].copy_from_slice(&RAW_MAGIC.to_le_bytes());
+        let checksum = compute_file_header_checksum(&block).ok_or(Error::InvalidRecord)?;
+        block[4..6].copy_from_slice(&checksum.to_le_bytes());
+        self.writer.write_all(&block)?;
+        self.writer.flush()?;
+        Ok(self.writer)
+    }
+
+    fn write_record(
+        &mut self,
+        header: RecordHeader,
+        name: &Path,
+        symlink: Option<&Path>,
+        data: &[u8],
+        magic: u16,
+    ) -> Result<()> {
+        self.write_record_head(header, name, symlink, magic)?;
+        self.writer.write_all(data)?;
+        self.write_padding(data.len() as u64)
+    }
+
+    /// Writes a record up to the start of its payload.
+    fn write_record_head(
+        &mut self,
+        mut header: RecordHeader,
+        name: &Path,
+        symlink: Option<&Path>,
+        magic: u16,
+    ) -> Result<()> {
+        let name = aligned_name(name)?;
+        // The first byte holds the length of header and name in 8-byte units,
+        // the same way the file header announces its own length.
+        header.unk00 = u8::try_from((std::mem::size_of::<RecordHeader>() + name.len()) / 8)
+            .map_err(|_| Error::InvalidRecord)?;
+        header.unk01 = RECORD_FORMAT;
+        header.magic = magic;
+
+        let mut block = struct_bytes(&header);
+        block.extend_from_slice(&name);
+        let checksum = compute_file_header_checksum(&block).ok_or(Error::InvalidRecord)?;
+        block[4..6].copy_from_slice(&checksum.to_le_bytes());
+        self.writer.write_all(&block)?;
+
+        if let Some(target) = symlink {
+            self.writer.write_all(&aligned_name(target)?)?;
+        }
+        self.writer
+            .write_all(&struct_bytes(&RecordAcl::default()))?;
+        Ok(())
+    }
+
+    /// Pads a payload of `len` bytes to the next 8-byte boundary.
+    fn write_padding(&mut self, len: u64) -> Result<()> {
+        let padding = (len.next_multiple_of(8) - len) as usize;
+        self.writer.write_all(&[0u8; 8][..padding])?;
+        Ok(())
+    }
+}
+
+/// Returns an archive containing the given files and their content.
+#[cfg(test)]
+pub(crate) fn archive_with_files(files: &[(&str, &[u8])]) -> Vec<u8> {
+    let mut writer = ArchiveWriter::new(Vec::new()).unwrap();
+    for (name, content) in files {
+        writer
+            .append_file(name, &Default::default(), content)
+            .unwrap();
+    }
+    writer.finish().unwrap()
+}
+
+/// Create a [RecordHeader] carrying the ownership and timestamps of `metadata`.
+fn record_header(metadata: &EntryMetadata) -> RecordHeader {
+    RecordHeader {
+        uid: metadata.uid,
+        gid: metadata.gid,
+        atime: metadata.atime,
+        mtime: metadata.mtime,
+        time24: metadata.ctime,
+        ..Default::default()
+    }
+}
+
+/// Encode a record path as NULL terminated string padded to the next 8-byte boundary.
+fn aligned_name(path: &Path) -> Result<Vec<u8>> {
+    let mut bytes = path
+        .components()
+        .map(|component| match component {
+            Component::RootDir => vec![],
+            component => bytes_from_os_str(component.as_os_str()),
+        })
+        .collect::<Vec<_>>()
+        .join(&b'/');
+    if bytes.is_empty() {
+        return Err(Error::EmptyFilename);
+    }
+    if bytes
+        .iter()
+        .any(|c| matches!(c, b'\0' | b'\n' | b'\t' | b'\x0B' | b'\x7F'))
+    {
+        return Err(Error::InvalidFilename(path.to_path_buf()));
+    }
+
+    bytes.resize((bytes.len() + 1).next_multiple_of(8), 0);
+    Ok(bytes)
+}
+
+/// Returns the on-disk representation of a packed header struct.
+fn struct_bytes<T: Copy>(value: &T) -> Vec<u8> {
+    let mut bytes = vec![0u8; std::mem::size_of::<T>()];
+    unsafe { std::ptr::write_unaligned(bytes.as_mut_ptr().cast::<T>(), *value) };
+    bytes
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::{Cursor, Read};
+
+    use tempfile::tempdir;
+
+    use super::*;
+    use crate::archive::Archive;
+
+    fn metadata() -> EntryMetadata {
+        EntryMetadata {
+            mode: 0o640,
+            uid: 204,
+            gid: 21800,
+            atime: 1_716_804_000,
+            mtime: 1_716_804_100,
+            ctime: 1_716_804_200,
+        }
+    }
+
+    #[test]
+    fn written_archive_reads_back() {
+        let mut writer = ArchiveWriter::new(Cursor::new(Vec::new())).unwrap();
+        writer.append_directory("./dir", &metadata()).unwrap();
+        writer
+            .append_file("./dir/file.txt", &metadata(), b"hello from bff\n")
+            .unwrap();
+        writer
+            .append_symlink("./dir/link", "file.txt", &metadata())
+            .unwrap();
+        let bytes = writer.finish().unwrap().into_inner();
+
+        let mut archive = Archive::new(Cursor::new(bytes)).unwrap();
+        let records = archive.records();
+        assert_eq!(records.len(), 3);
+        assert!(records[0].mode().file_type().unwrap().is_directory());
+        assert_eq!(records[1].filename(), Path::new("./dir/file.txt"));
+        assert_eq!(records[1].size(), 15);
+        assert_eq!(records[1].uid(), 204);
+        assert_eq!(records[1].gid(), 21800);
+        assert_eq!(records[1].mode().mode() & 0o7777, 0o640);
+        assert_eq!(records[1].mdate().and_utc().timestamp(), 1_716_804_100);
+        assert_eq!(records[2].symlink(), Some(Path::new("file.txt")));
+
+        let mut content = String::new();
+        archive
+            .file("./dir/file.txt")
+            .unwrap()
+            .unwrap()
+            .read_to_string(&mut content)
+            .unwrap();
+        assert_eq!(content, "hello from bff\n");
+    }
+
+    #[test]
+    fn written_headers_carry_valid_checksums() {
+        let mut writer = ArchiveWriter::new(Vec::new()).unwrap();
+        writer
+            .append_file("./a/long/file/name.txt", &metadata(), b"abc")
+            .unwrap();
+        let bytes = writer.finish().unwrap();
+
+        let file_header_checksum = u16::from_le_bytes([bytes[4], bytes[5]]);
+        assert_eq!(
+            compute_file_header_checksum(&bytes[..72]),
+            Some(file_header_checksum)
+        );
+
+        let record = &bytes[72..];
+        assert_eq!(record[0], 11);
+        let record_checksum = u16::from_le_bytes([record[4], record[5]]);
+        assert_eq!(compute_file_header_checksum(record), Some(record_checksum));
+    }
+
+    #[test]
+    fn append_dir_all_writes_tree() {
+        let temp_dir = tempdir().unwrap();
+        let source = temp_dir.path().join("source");
+        fs::create_dir_all(source.join("folder")).unwrap();
+        fs::write(source.join("file.txt"), b"abc").unwrap();
+        fs::write(source.join("folder/inner.txt"), b"xyz").unwrap();
+
+        let mut writer = ArchiveWriter::new(Cursor::new(Vec::new())).unwrap();
+        writer.append_dir_all(&source, "backup").unwrap();
+        let bytes = writer.finish().unwrap().into_inner();
+
+        let mut archive = Archive::new(Cursor::new(bytes)).unwrap();
+        let names: Vec<_> = archive
+            .records()
+            .iter()
+            .map(|record| record.filename().to_string_lossy().into_owned())
+            .collect();
+        assert_eq!(
+            names,
+            vec![
+                "backup",
+                "backup/file.txt",
+                "backup/folder",
+                "backup/folder/inner.txt"
+            ]
+        );
+
+        let output = temp_dir.path().join("output");
+        archive.extract(&output).unwrap();
+        assert_eq!(
+            fs::read(output.join("backup/folder/inner.txt")).unwrap(),
+            b"xyz"
+        );
+    }
+
+    #[test]
+    fn written_compressed_file_reads_back() {
+        let data = b"compressed content, compressed content, compressed content".repeat(8);
+        let mut writer = ArchiveWriter::new(Cursor::new(Vec::new())).unwrap();
+        writer
+            .append_file_compressed("./file.txt", &metadata(), &data)
+            .unwrap();
+        let bytes = writer.finish().unwrap().into_inner();
+
+        let mut archive = Archive::new(Cursor::new(bytes)).unwrap();
+        let record = &archive.records()[0];
+        assert_eq!(record.magic(), HUFFMAN_MAGIC);
+        assert_eq!(record.size(), data.len() as u64);
+        assert!(record.compressed_size() < record.size());
+
+        let mut content = vec![];
+        archive
+            .file("./file.txt")
+            .unwrap()
+            .unwrap()
+            .read_to_end(&mut content)
+            .unwrap();
+        assert_eq!(content, data);
+    }
+
+    #[test]
+    fn append_reader_streams_content() {
+        let mut writer = ArchiveWriter::new(Cursor::new(Vec::new())).unwrap();
+        let data = b"streamed content".repeat(100);
+        writer
+            .append_reader("./file.txt", &metadata(), 1000, data.as_slice())
+            .unwrap();
+        let bytes = writer.finish().unwrap().into_inner();
+
+        let mut archive = Archive::new(Cursor::new(bytes)).unwrap();
+        assert_eq!(archive.records()[0].size(), 1000);
+        let mut content = vec![];
+        archive
+            .file("./file.txt")
+            .unwrap()
+            .unwrap()
+            .read_to_end(&mut content)
+            .unwrap();
+        assert_eq!(content, data[..1000]);
+    }
+
+    #[test]
+    fn append_reader_rejects_short_reader() {
+        let mut writer = ArchiveWriter::new(Vec::new()).unwrap();
+
+        let error = writer
+            .append_reader("./file.txt", &metadata(), 10, b"short".as_slice())
+            .unwrap_err();
+        assert!(matches!(
+            error,
+            Error::IoError(error) if error.kind() == io::ErrorKind::UnexpectedEof
+        ));
+    }
+
+    #[test]
+    fn rejects_files_beyond_4_gib() {
+        let mut writer = ArchiveWriter::new(Vec::new()).unwrap();
+
+        assert!(matches!(
+            writer.append_reader("./big", &metadata(), 1 << 32, io::empty()),
+            Err(Error::FileToBig)
+        ));
+        assert_eq!(writer.finish().unwrap().len(), 72 + 8);
+    }
+
+    #[test]
+    fn rejects_unrepresentable_names() {
+        let mut writer = ArchiveWriter::new(Vec::new()).unwrap();
+
+        assert!(matches!(
+            writer.append_file("", &metadata(), b""),
+            Err(Error::EmptyFilename)
+        ));
+        assert!(matches!(
+            writer.append_file("bad\nname", &metadata(), b""),
+            Err(Error::InvalidFilename(_))
+        ));
+    }
+}
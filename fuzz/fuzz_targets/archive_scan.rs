@@ -0,0 +1,28 @@
+#![no_main]
+
+use std::io::{Cursor, Read};
+
+use bfflib::archive::Archive;
+use libfuzzer_sys::fuzz_target;
+
+fuzz_target!(|data: &[u8]| {
+    let Ok(mut archive) = Archive::scan(Cursor::new(data)) else {
+        return;
+    };
+    let filenames: Vec<_> = archive
+        .records()
+        .iter()
+        .filter(|record| {
+            record
+                .mode()
+                .file_type()
+                .is_some_and(|file_type| file_type.is_regular_file())
+        })
+        .map(|record| record.filename().to_path_buf())
+        .collect();
+    for filename in filenames {
+        if let Ok(Some(mut reader)) = archive.file(&filename) {
+            let _ = reader.read_to_end(&mut Vec::new());
+        }
+    }
+});
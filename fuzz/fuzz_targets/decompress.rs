@@ -0,0 +1,13 @@
+#![no_main]
+
+use std::io::Read;
+
+use bfflib::compression::decompress;
+use libfuzzer_sys::fuzz_target;
+
+fuzz_target!(|data: &[u8]| {
+    // Limit the output, compressed data can expand a lot.
+    if let Ok(decoder) = decompress(data) {
+        let _ = decoder.take(16 * 1024 * 1024).read_to_end(&mut Vec::new());
+    }
+});
@@ -0,0 +1,20 @@
+#![no_main]
+
+use std::io::Read;
+
+use bfflib::huffman::HuffmanDecoder;
+use libfuzzer_sys::fuzz_target;
+
+fuzz_target!(|data: &[u8]| {
+    // The first 4 bytes are used as expected length, the remaining bytes as compressed stream.
+    let Some((expected_len, stream)) = data.split_first_chunk::<4>() else {
+        return;
+    };
+    let expected_len = u64::from(u32::from_le_bytes(*expected_len));
+    if let Ok(mut decoder) = HuffmanDecoder::with_expected_len(stream, expected_len) {
+        let _ = decoder.read_to_end(&mut Vec::new());
+    }
+    if let Ok(mut decoder) = HuffmanDecoder::new(stream) {
+        let _ = decoder.read_to_end(&mut Vec::new());
+    }
+});